@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS chats (
+    id INTEGER PRIMARY KEY,
+    situation TEXT NOT NULL,
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    agent_id INTEGER,
+    tabulation TEXT,
+    customer_id INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS chats_customer_id_idx ON chats (customer_id);
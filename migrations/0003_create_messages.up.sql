@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    "from" TEXT NOT NULL,
+    "to" TEXT NOT NULL,
+    text TEXT NOT NULL,
+    delivered BOOLEAN NOT NULL DEFAULT FALSE,
+    chat_id INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS messages_chat_id_idx ON messages (chat_id);
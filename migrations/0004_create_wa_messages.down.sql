@@ -0,0 +1 @@
+DROP TABLE IF EXISTS wa_messages;
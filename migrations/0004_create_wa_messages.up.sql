@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS wa_messages (
+    instance_id TEXT NOT NULL,
+    wa_message_id TEXT NOT NULL,
+    chat_id TEXT NOT NULL,
+    direction TEXT NOT NULL CHECK (direction IN ('incoming', 'outgoing')),
+    message_type TEXT NOT NULL,
+    "from" TEXT NOT NULL,
+    "to" TEXT NOT NULL,
+    text TEXT NOT NULL,
+    body TEXT,
+    caption TEXT,
+    mime_type TEXT,
+    file_name TEXT,
+    quoted_id TEXT,
+    media_key TEXT,
+    media_url TEXT,
+    media_size BIGINT,
+    media_sha256 TEXT,
+    sent_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (instance_id, wa_message_id)
+);
+
+CREATE INDEX IF NOT EXISTS wa_messages_chat_id_sent_at_idx ON wa_messages (chat_id, sent_at);
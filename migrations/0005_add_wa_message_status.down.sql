@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS wa_message_statuses;
+
+ALTER TABLE wa_messages
+    DROP COLUMN IF EXISTS status_at,
+    DROP COLUMN IF EXISTS status;
@@ -0,0 +1,10 @@
+ALTER TABLE wa_messages
+    ADD COLUMN IF NOT EXISTS status TEXT,
+    ADD COLUMN IF NOT EXISTS status_at TIMESTAMPTZ;
+
+CREATE TABLE IF NOT EXISTS wa_message_statuses (
+    wa_message_id TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'sent', 'delivered', 'read', 'played', 'failed')),
+    occurred_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (wa_message_id, status)
+);
@@ -0,0 +1,10 @@
+-- Keeps the earliest row of each (wa_message_id, status) so the old key can be restored.
+DELETE FROM wa_message_statuses newer
+    USING wa_message_statuses older
+    WHERE newer.wa_message_id = older.wa_message_id
+      AND newer.status = older.status
+      AND (newer.occurred_at, newer.instance_id) > (older.occurred_at, older.instance_id);
+
+ALTER TABLE wa_message_statuses DROP CONSTRAINT IF EXISTS wa_message_statuses_pkey;
+ALTER TABLE wa_message_statuses ADD PRIMARY KEY (wa_message_id, status);
+ALTER TABLE wa_message_statuses DROP COLUMN IF EXISTS instance_id;
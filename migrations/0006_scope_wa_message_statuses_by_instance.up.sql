@@ -0,0 +1,5 @@
+ALTER TABLE wa_message_statuses
+    ADD COLUMN IF NOT EXISTS instance_id TEXT NOT NULL DEFAULT '';
+
+ALTER TABLE wa_message_statuses DROP CONSTRAINT IF EXISTS wa_message_statuses_pkey;
+ALTER TABLE wa_message_statuses ADD PRIMARY KEY (instance_id, wa_message_id, status);
@@ -0,0 +1,182 @@
+use serde_json::{json, Map, Value};
+use log::info;
+use crate::api::provider::{compact, json_request, recipient, WhatsAppProvider};
+use crate::api::requests::status_error;
+use crate::config::config::ApiEndpoints;
+use crate::parser::library::{
+    CheckWhatsAppNumbers, DeleteMessage, MarkMessageAsRead, MediaFetch, OutboundTarget, Request, SendAudio,
+    SendContact, SendLocation, SendMedia, SendPresence, SendReaction, SendText,
+};
+use crate::phone::normalize::PhoneNormalizer;
+use crate::process::error::ProcessError;
+
+pub struct EvolutionClient<'a> {
+    phone: &'a PhoneNormalizer,
+    base_url: String,
+    apikey: String,
+    instance: String,
+}
+
+impl<'a> EvolutionClient<'a> {
+    pub fn for_instance(phone: &'a PhoneNormalizer, config: &ApiEndpoints, instance: &str) -> Result<Self, ProcessError> {
+        let (base_url, apikey) = config.resolve(instance).ok_or_else(|| {
+            ProcessError::Permanent(format!("No Evolution URL/apikey configured for instance '{}'", instance))
+        })?;
+        Ok(EvolutionClient {
+            phone,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            apikey,
+            instance: instance.to_string(),
+        })
+    }
+
+    fn request(&self, action: &str, method: &str, path: &str, body: Value) -> Request {
+        let url = format!("{}/{}/{}", self.base_url, path, self.instance);
+        json_request(action, method, url, ("apikey", &self.apikey), body)
+    }
+
+    fn remote_jid(&self, number: &str) -> String {
+        self.phone.chat_id_for_number(number)
+    }
+
+    fn message_body(&self, target: &OutboundTarget, fields: Value) -> Value {
+        let mut body = Map::new();
+        body.insert("number".to_string(), json!(recipient(self.phone, &target.number)));
+        if let Some(delay) = target.delay {
+            body.insert("delay".to_string(), json!(delay));
+        }
+        if let Value::Object(fields) = compact(fields) {
+            body.extend(fields);
+        }
+        Value::Object(body)
+    }
+}
+
+impl WhatsAppProvider for EvolutionClient<'_> {
+    fn send_text(&self, op: &SendText) -> Result<Request, ProcessError> {
+        let quoted = op.quoted_id.as_ref().map(|id| json!({ "key": { "id": id } }));
+        let body = self.message_body(&op.target, json!({
+            "text": op.text,
+            "linkPreview": op.link_preview,
+            "quoted": quoted,
+        }));
+        Ok(self.request("sendText", "POST", "message/sendText", body))
+    }
+
+    fn send_media(&self, op: &SendMedia) -> Result<Request, ProcessError> {
+        let body = self.message_body(&op.target, json!({
+            "mediatype": op.media_type.to_ascii_lowercase(),
+            "media": op.media,
+            "mimetype": op.mime_type,
+            "caption": op.caption,
+            "fileName": op.file_name,
+        }));
+        Ok(self.request("sendMedia", "POST", "message/sendMedia", body))
+    }
+
+    fn send_audio(&self, op: &SendAudio) -> Result<Request, ProcessError> {
+        let body = self.message_body(&op.target, json!({ "audio": op.audio }));
+        Ok(self.request("sendAudio", "POST", "message/sendWhatsAppAudio", body))
+    }
+
+    fn send_location(&self, op: &SendLocation) -> Result<Request, ProcessError> {
+        let body = self.message_body(&op.target, json!({
+            "latitude": op.latitude,
+            "longitude": op.longitude,
+            "name": op.name,
+            "address": op.address,
+        }));
+        Ok(self.request("sendLocation", "POST", "message/sendLocation", body))
+    }
+
+    fn send_contact(&self, op: &SendContact) -> Result<Request, ProcessError> {
+        let contacts: Vec<Value> = op.contacts
+            .iter()
+            .map(|contact| compact(json!({
+                "fullName": contact.full_name,
+                "wuid": recipient(self.phone, &contact.phone_number),
+                "phoneNumber": contact.phone_number,
+                "organization": contact.organization,
+                "email": contact.email,
+                "url": contact.url,
+            })))
+            .collect();
+        let body = self.message_body(&op.target, json!({ "contact": contacts }));
+        Ok(self.request("sendContact", "POST", "message/sendContact", body))
+    }
+
+    fn send_reaction(&self, op: &SendReaction) -> Result<Request, ProcessError> {
+        let body = json!({
+            "key": {
+                "remoteJid": self.remote_jid(&op.target.number),
+                "fromMe": op.from_me,
+                "id": op.message_id,
+            },
+            "reaction": op.reaction,
+        });
+        Ok(self.request("sendReaction", "POST", "message/sendReaction", body))
+    }
+
+    fn send_presence(&self, op: &SendPresence) -> Result<Request, ProcessError> {
+        let mut body = self.message_body(&op.target, json!({ "presence": op.presence }));
+        if body.get("delay").is_none() {
+            body["delay"] = json!(0);
+        }
+        Ok(self.request("sendPresence", "POST", "chat/sendPresence", body))
+    }
+
+    fn mark_message_as_read(&self, op: &MarkMessageAsRead) -> Result<Request, ProcessError> {
+        let remote_jid = self.remote_jid(&op.target.number);
+        let messages: Vec<Value> = op.message_ids
+            .iter()
+            .map(|id| json!({ "remoteJid": remote_jid, "fromMe": false, "id": id }))
+            .collect();
+        Ok(self.request("markMessageAsRead", "POST", "chat/markMessageAsRead", json!({ "readMessages": messages })))
+    }
+
+    fn delete_message(&self, op: &DeleteMessage) -> Result<Request, ProcessError> {
+        let mut body = json!({
+            "id": op.message_id,
+            "remoteJid": self.remote_jid(&op.target.number),
+            "fromMe": op.from_me,
+        });
+        if let Some(participant) = &op.participant {
+            body["participant"] = json!(self.remote_jid(participant));
+        }
+        Ok(self.request("deleteMessage", "DELETE", "chat/deleteMessageForEveryone", body))
+    }
+
+    fn check_whatsapp_numbers(&self, op: &CheckWhatsAppNumbers) -> Result<Request, ProcessError> {
+        let numbers: Vec<String> = op.numbers.iter().map(|number| recipient(self.phone, number)).collect();
+        Ok(self.request("checkWhatsAppNumbers", "POST", "chat/whatsappNumbers", json!({ "numbers": numbers })))
+    }
+}
+
+pub async fn get_base64_from_media_message(client: &reqwest::Client, fetch: &MediaFetch) -> Result<String, ProcessError> {
+    let url = format!(
+        "{}/chat/getBase64FromMediaMessage/{}",
+        fetch.server_url.trim_end_matches('/'),
+        fetch.instance
+    );
+    info!("Fetching media for message {} from Evolution", fetch.message_id);
+
+    let response = client
+        .post(&url)
+        .header("apikey", &fetch.apikey)
+        .header("Content-Type", "application/json")
+        .body(json!({
+            "message": { "key": { "id": fetch.message_id } },
+            "convertToMp4": false
+        }).to_string())
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(status_error(response.status()));
+    }
+
+    let body: Value = serde_json::from_str(&response.text().await?)?;
+    body.get("base64")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| ProcessError::Permanent("Evolution didn't return base64 for media message".to_string()))
+}
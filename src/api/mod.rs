@@ -1 +1,4 @@
-pub mod requests;
\ No newline at end of file
+pub mod requests;
+pub mod evolution;
+pub mod wuzapi;
+pub mod provider;
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use serde_json::Value;
+use crate::api::evolution::EvolutionClient;
+use crate::api::wuzapi::WuzapiClient;
+use crate::config::config::DotEnv;
+use crate::parser::library::{
+    CheckWhatsAppNumbers, DeleteMessage, MarkMessageAsRead, Request, SendAudio, SendContact, SendLocation,
+    SendMedia, SendPresence, SendReaction, SendText,
+};
+use crate::parser::provider::Provider;
+use crate::phone::normalize::PhoneNormalizer;
+use crate::process::error::ProcessError;
+
+// Each operation is turned into a plain `Request`, so every provider shares the HTTP executor and
+// the result publishing of `sendRequest`.
+pub trait WhatsAppProvider {
+    fn send_text(&self, op: &SendText) -> Result<Request, ProcessError>;
+    fn send_media(&self, op: &SendMedia) -> Result<Request, ProcessError>;
+    fn send_audio(&self, op: &SendAudio) -> Result<Request, ProcessError>;
+    fn send_location(&self, op: &SendLocation) -> Result<Request, ProcessError>;
+    fn send_contact(&self, op: &SendContact) -> Result<Request, ProcessError>;
+    fn send_reaction(&self, op: &SendReaction) -> Result<Request, ProcessError>;
+    fn send_presence(&self, op: &SendPresence) -> Result<Request, ProcessError>;
+    fn mark_message_as_read(&self, op: &MarkMessageAsRead) -> Result<Request, ProcessError>;
+    fn delete_message(&self, op: &DeleteMessage) -> Result<Request, ProcessError>;
+    fn check_whatsapp_numbers(&self, op: &CheckWhatsAppNumbers) -> Result<Request, ProcessError>;
+}
+
+pub fn provider_for<'a>(env: &DotEnv, phone: &'a PhoneNormalizer, instance: &str) -> Result<Box<dyn WhatsAppProvider + 'a>, ProcessError> {
+    match env.providers.for_instance(instance) {
+        Provider::Evolution => Ok(Box::new(EvolutionClient::for_instance(phone, &env.evolution, instance)?)),
+        Provider::Wuzapi => Ok(Box::new(WuzapiClient::for_instance(phone, &env.wuzapi, instance)?)),
+    }
+}
+
+pub fn unsupported(provider: &str, operation: &str, reason: &str) -> ProcessError {
+    ProcessError::Permanent(format!("{} doesn't support {}: {}", provider, operation, reason))
+}
+
+// Providers take plain numbers for users and full JIDs for groups.
+pub fn recipient(phone: &PhoneNormalizer, number: &str) -> String {
+    if number.contains('@') {
+        return phone.normalize_jid(number);
+    }
+    phone.normalize_number(number).unwrap_or_else(|| number.to_string())
+}
+
+pub fn json_request(action: &str, method: &str, url: String, auth: (&str, &str), body: Value) -> Request {
+    let mut headers = HashMap::new();
+    headers.insert(auth.0.to_string(), auth.1.to_string());
+    headers.insert("Content-Type".to_string(), "application/json".to_string());
+    Request {
+        action: action.to_string(),
+        method: method.to_string(),
+        url,
+        headers,
+        body: Some(body),
+        params: None,
+        correlation_id: None,
+        instance: None,
+    }
+}
+
+// Drops nulls so optional fields aren't sent as `null` to providers that validate types strictly.
+pub fn compact(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(fields.into_iter().filter(|(_, value)| !value.is_null()).collect()),
+        other => other,
+    }
+}
@@ -1,77 +1,143 @@
-use reqwest;
-use crate::parser;
-use log::{info, error};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use reqwest::{Client, Method};
+use serde_json::Value;
+use log::{info, error, debug};
+use crate::config::config::HttpConfig;
+use crate::parser::library::Request;
+use crate::process::error::ProcessError;
 
-pub async fn make_request(request : parser::library::Request) -> Result<(), Box<dyn std::error::Error>> {
-    let client = reqwest::Client::new();
-    info!("Started making request for : {}", request.action);
+pub fn build_client(config: &HttpConfig) -> reqwest::Result<Client> {
+    Client::builder()
+        .connect_timeout(config.connect_timeout)
+        .read_timeout(config.read_timeout)
+        .build()
+}
 
-    if request.method == "POST" {
-        let mut req = client.post(&request.url);
+pub fn status_error(status: reqwest::StatusCode) -> ProcessError {
+    let message = format!("Request failed with status: {}", status);
+    if status.is_server_error()
+        || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        || status == reqwest::StatusCode::REQUEST_TIMEOUT {
+        ProcessError::Transient(message)
+    } else {
+        ProcessError::Permanent(message)
+    }
+}
 
-        for (key, value) in &request.headers {
-            req = req.header(key, value);
-            info!("Headers: {} : {}",key, value);
-        }
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(v) => v.clone(),
+        other => other.to_string(),
+    }
+}
 
-        if let Some(body) = &request.body {
-            let body_json = serde_json::to_string(body)?;
-            info!("Body: {}", &body_json);
-            req = req.body(body_json);
+// Arrays repeat the key (`?id=1&id=2`), which is what Evolution and most REST APIs expect.
+fn pairs(value: &Value) -> Result<Vec<(String, String)>, ProcessError> {
+    let Some(object) = value.as_object() else {
+        return Err(ProcessError::Permanent("Query params and form bodies must be JSON objects".to_string()));
+    };
+    let mut pairs = Vec::new();
+    for (key, value) in object {
+        match value {
+            Value::Array(items) => pairs.extend(items.iter().map(|item| (key.clone(), scalar_to_string(item)))),
+            Value::Object(_) => pairs.push((key.clone(), value.to_string())),
+            other => pairs.push((key.clone(), scalar_to_string(other))),
         }
+    }
+    Ok(pairs)
+}
 
-        let response = req.send().await?;
-        if !response.status().is_success() {
-            error!("Request failed with status: {}", response.status());
-            return Err(format!("Request failed with status: {}", response.status()).into());
-        }
-        
-        let response_text = response.text().await?;
-        info!("Response body: {}", response_text);
-    } else if request.method == "GET" {
-        let mut req = client.get(&request.url);
-
-        for (key, value) in &request.headers {
-            req = req.header(key, value);
-        }
+fn header_map(request: &Request) -> Result<HeaderMap, ProcessError> {
+    let mut headers = HeaderMap::new();
+    for (key, value) in &request.headers {
+        let name = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|e| ProcessError::Permanent(format!("Invalid header name '{}': {}", key, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| ProcessError::Permanent(format!("Invalid value for header '{}': {}", key, e)))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
 
-        if let Some(body) = &request.body {
-            let body_json = serde_json::to_string(body)?;
-            req = req.body(body_json);
-        }
+fn with_body(req: reqwest::RequestBuilder, headers: &HeaderMap, body: &Value) -> Result<reqwest::RequestBuilder, ProcessError> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.split(';').next().unwrap_or("").trim().to_ascii_lowercase());
 
-        let response = req.send().await?;
-        if !response.status().is_success() {
-            error!("Request failed with status: {}", response.status());
-            return Err(format!("Request failed with status: {}", response.status()).into());
+    match content_type.as_deref() {
+        Some("application/x-www-form-urlencoded") => {
+            debug!("Form body: {}", body);
+            Ok(req.form(&pairs(body)?))
         }
-        
-        let response_text = response.text().await?;
-        info!("Response body: {}", response_text);
-    } else if request.method == "DELETE" {
-        let mut req = client.delete(&request.url);
-
-        for (key, value) in &request.headers {
-            req = req.header(key, value);
+        None => Ok(req.header(CONTENT_TYPE, "application/json").body(body.to_string())),
+        Some(content_type) if content_type == "application/json" || content_type.ends_with("+json") => {
+            Ok(req.body(body.to_string()))
         }
+        // Anything else (text/plain, XML, ...) is sent as is when the body is a string.
+        Some(_) => match body {
+            Value::String(raw) => Ok(req.body(raw.clone())),
+            other => Ok(req.body(other.to_string())),
+        },
+    }
+}
 
-        if let Some(body) = &request.body {
-            let body_json = serde_json::to_string(body)?;
-            req = req.body(body_json);
-        }
+// Only headers a caller can act on are forwarded with the result.
+const FORWARDED_HEADERS: [&str; 4] = ["content-type", "location", "retry-after", "x-request-id"];
+const FORWARDED_HEADER_PREFIX: &str = "x-ratelimit-";
 
-        let response = req.send().await?;
-        if !response.status().is_success() {
-            error!("Request failed with status: {}", response.status());
-            return Err(format!("Request failed with status: {}", response.status()).into());
-        }
-        
-        let response_text = response.text().await?;
-        info!("Response body: {}", response_text);
+pub struct HttpResponse {
+    pub status: reqwest::StatusCode,
+    pub headers: serde_json::Map<String, Value>,
+    pub body: Value,
+}
+
+impl HttpResponse {
+    pub fn error(&self) -> Option<ProcessError> {
+        (!self.status.is_success()).then(|| status_error(self.status))
+    }
+}
+
+fn forwarded_headers(headers: &HeaderMap) -> serde_json::Map<String, Value> {
+    headers
+        .iter()
+        .filter(|(name, _)| FORWARDED_HEADERS.contains(&name.as_str()) || name.as_str().starts_with(FORWARDED_HEADER_PREFIX))
+        .filter_map(|(name, value)| Some((name.as_str().to_string(), Value::String(value.to_str().ok()?.to_string()))))
+        .collect()
+}
+
+// Resolves to Ok for any HTTP status; only invalid requests and transport failures are errors.
+pub async fn make_request(client: &Client, request: &Request) -> Result<HttpResponse, ProcessError> {
+    info!("Started making request for : {}", request.action);
+
+    let method = Method::from_bytes(request.method.trim().to_ascii_uppercase().as_bytes())
+        .map_err(|_| ProcessError::Permanent(format!("Couldn't make request, invalid method '{}'", request.method)))?;
+    let headers = header_map(request)?;
+
+    let mut req = client.request(method.clone(), &request.url).headers(headers.clone());
+    if let Some(params) = &request.params {
+        req = req.query(&pairs(params)?);
+    }
+    if let Some(body) = request.body.as_ref().filter(|body| !body.is_null()) {
+        req = with_body(req, &headers, body)?;
+    }
+
+    let response = req.send().await?;
+    let status = response.status();
+    info!("{} {} returned {}", method, response.url(), status);
+    let response_headers = forwarded_headers(response.headers());
+    let text = if method == Method::HEAD { String::new() } else { response.text().await? };
+    if status.is_success() {
+        info!("Response body: {}", text);
     } else {
-        error!("Couldn't make request, method was neither POST, nor GET, nor DELETE.");
-        return Err("Couldn't make request, method was neither POST, nor GET, nor DELETE.".into());
+        error!("Request failed with status: {} - {}", status, text);
     }
 
-    Ok(())
-}
\ No newline at end of file
+    let body = if text.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(&text).unwrap_or(Value::String(text))
+    };
+    Ok(HttpResponse { status, headers: response_headers, body })
+}
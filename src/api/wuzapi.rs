@@ -0,0 +1,192 @@
+use serde_json::{json, Value};
+use crate::api::provider::{compact, json_request, recipient, unsupported, WhatsAppProvider};
+use crate::config::config::ApiEndpoints;
+use crate::parser::library::{
+    CheckWhatsAppNumbers, DeleteMessage, MarkMessageAsRead, Request, SendAudio, SendContact, SendLocation,
+    SendMedia, SendPresence, SendReaction, SendText,
+};
+use crate::phone::normalize::PhoneNormalizer;
+use crate::process::error::ProcessError;
+
+const PROVIDER: &str = "Wuzapi";
+
+// Wuzapi authenticates each user (instance) with its own token instead of putting the instance in the URL.
+pub struct WuzapiClient<'a> {
+    phone: &'a PhoneNormalizer,
+    base_url: String,
+    token: String,
+}
+
+impl<'a> WuzapiClient<'a> {
+    pub fn for_instance(phone: &'a PhoneNormalizer, config: &ApiEndpoints, instance: &str) -> Result<Self, ProcessError> {
+        let (base_url, token) = config.resolve(instance).ok_or_else(|| {
+            ProcessError::Permanent(format!("No Wuzapi URL/token configured for instance '{}'", instance))
+        })?;
+        Ok(WuzapiClient {
+            phone,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+        })
+    }
+
+    fn request(&self, action: &str, path: &str, body: Value) -> Request {
+        let url = format!("{}/{}", self.base_url, path);
+        json_request(action, "POST", url, ("Token", &self.token), compact(body))
+    }
+
+    fn phone_of(&self, number: &str) -> String {
+        recipient(self.phone, number)
+    }
+}
+
+// Wuzapi only takes media as data URLs; plain base64 gets wrapped and URLs are passed through.
+fn data_url(media: &str, mime_type: Option<&str>, default_mime: &str) -> String {
+    if media.starts_with("data:") || media.starts_with("http://") || media.starts_with("https://") {
+        return media.to_string();
+    }
+    format!("data:{};base64,{}", mime_type.unwrap_or(default_mime), media)
+}
+
+fn escape_vcard(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(',', "\\,").replace(';', "\\;").replace('\n', "\\n")
+}
+
+impl WhatsAppProvider for WuzapiClient<'_> {
+    fn send_text(&self, op: &SendText) -> Result<Request, ProcessError> {
+        // The quoted message may be ours or the contact's, so the participant is only sent when the
+        // producer says who wrote it.
+        let context = op.quoted_id.as_ref().map(|id| compact(json!({
+            "StanzaId": id,
+            "Participant": op.quoted_participant.as_deref().map(|participant| self.phone.chat_id_for_number(participant)),
+        })));
+        let body = json!({
+            "Phone": self.phone_of(&op.target.number),
+            "Body": op.text,
+            "LinkPreview": op.link_preview,
+            "ContextInfo": context,
+        });
+        Ok(self.request("sendText", "chat/send/text", body))
+    }
+
+    fn send_media(&self, op: &SendMedia) -> Result<Request, ProcessError> {
+        let phone = self.phone_of(&op.target.number);
+        let mime_type = op.mime_type.as_deref();
+        let (path, body) = match op.media_type.to_ascii_lowercase().as_str() {
+            "image" => ("chat/send/image", json!({
+                "Phone": phone,
+                "Image": data_url(&op.media, mime_type, "image/jpeg"),
+                "Caption": op.caption,
+            })),
+            "video" => ("chat/send/video", json!({
+                "Phone": phone,
+                "Video": data_url(&op.media, mime_type, "video/mp4"),
+                "Caption": op.caption,
+            })),
+            "document" => ("chat/send/document", json!({
+                "Phone": phone,
+                "Document": data_url(&op.media, mime_type, "application/octet-stream"),
+                "FileName": op.file_name.as_deref().unwrap_or("document"),
+                "Caption": op.caption,
+            })),
+            other => return Err(unsupported(PROVIDER, "sendMedia", &format!("unknown media type '{}'", other))),
+        };
+        Ok(self.request("sendMedia", path, body))
+    }
+
+    fn send_audio(&self, op: &SendAudio) -> Result<Request, ProcessError> {
+        let body = json!({
+            "Phone": self.phone_of(&op.target.number),
+            "Audio": data_url(&op.audio, None, "audio/ogg"),
+        });
+        Ok(self.request("sendAudio", "chat/send/audio", body))
+    }
+
+    fn send_location(&self, op: &SendLocation) -> Result<Request, ProcessError> {
+        let body = json!({
+            "Phone": self.phone_of(&op.target.number),
+            "Latitude": op.latitude,
+            "Longitude": op.longitude,
+            "Name": op.name.as_ref().or(op.address.as_ref()),
+        });
+        Ok(self.request("sendLocation", "chat/send/location", body))
+    }
+
+    fn send_contact(&self, op: &SendContact) -> Result<Request, ProcessError> {
+        let [contact] = op.contacts.as_slice() else {
+            return Err(unsupported(PROVIDER, "sendContact", "exactly one contact per message is accepted"));
+        };
+        let wuid = recipient(self.phone, &contact.phone_number);
+        let mut vcard = format!(
+            "BEGIN:VCARD\nVERSION:3.0\nFN:{}\nTEL;type=CELL;type=VOICE;waid={}:+{}\n",
+            escape_vcard(&contact.full_name), wuid, wuid
+        );
+        if let Some(organization) = &contact.organization {
+            vcard.push_str(&format!("ORG:{}\n", escape_vcard(organization)));
+        }
+        if let Some(email) = &contact.email {
+            vcard.push_str(&format!("EMAIL:{}\n", escape_vcard(email)));
+        }
+        if let Some(url) = &contact.url {
+            vcard.push_str(&format!("URL:{}\n", escape_vcard(url)));
+        }
+        vcard.push_str("END:VCARD");
+        let body = json!({
+            "Phone": self.phone_of(&op.target.number),
+            "Name": contact.full_name,
+            "Vcard": vcard,
+        });
+        Ok(self.request("sendContact", "chat/send/contact", body))
+    }
+
+    fn send_reaction(&self, op: &SendReaction) -> Result<Request, ProcessError> {
+        // Reactions to our own messages are addressed with a `me:` prefix.
+        let id = if op.from_me { format!("me:{}", op.message_id) } else { op.message_id.clone() };
+        let body = json!({
+            "Phone": self.phone_of(&op.target.number),
+            "Body": op.reaction,
+            "Id": id,
+        });
+        Ok(self.request("sendReaction", "chat/react", body))
+    }
+
+    fn send_presence(&self, op: &SendPresence) -> Result<Request, ProcessError> {
+        let (state, media) = match op.presence.to_ascii_lowercase().as_str() {
+            "composing" => ("composing", ""),
+            "recording" => ("composing", "audio"),
+            "paused" | "available" => ("paused", ""),
+            other => return Err(unsupported(PROVIDER, "sendPresence", &format!("unknown presence '{}'", other))),
+        };
+        let body = json!({
+            "Phone": self.phone_of(&op.target.number),
+            "State": state,
+            "Media": media,
+        });
+        Ok(self.request("sendPresence", "chat/presence", body))
+    }
+
+    fn mark_message_as_read(&self, op: &MarkMessageAsRead) -> Result<Request, ProcessError> {
+        let chat = self.phone.chat_id_for_number(&op.target.number);
+        let body = json!({
+            "Id": op.message_ids,
+            "Chat": chat,
+            "Sender": chat,
+        });
+        Ok(self.request("markMessageAsRead", "chat/markread", body))
+    }
+
+    fn delete_message(&self, op: &DeleteMessage) -> Result<Request, ProcessError> {
+        if !op.from_me {
+            return Err(unsupported(PROVIDER, "deleteMessage", "only messages sent by the instance can be deleted"));
+        }
+        let body = json!({
+            "Phone": self.phone_of(&op.target.number),
+            "Id": op.message_id,
+        });
+        Ok(self.request("deleteMessage", "chat/delete", body))
+    }
+
+    fn check_whatsapp_numbers(&self, op: &CheckWhatsAppNumbers) -> Result<Request, ProcessError> {
+        let numbers: Vec<String> = op.numbers.iter().map(|number| self.phone_of(number)).collect();
+        Ok(self.request("checkWhatsAppNumbers", "user/check", json!({ "Phone": numbers })))
+    }
+}
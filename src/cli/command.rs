@@ -0,0 +1,56 @@
+pub enum Command {
+    Consume,
+    Migrate(MigrateCommand),
+    MigrateRedis,
+    MergeChats { dry_run: bool },
+    EventsToken { instances: Vec<String>, ttl_secs: i64 },
+}
+
+pub enum MigrateCommand {
+    Up,
+    Down(usize),
+    Status,
+}
+
+pub const USAGE: &str = "usage: WaSolConsumer [migrate <up|down [steps]|status|redis> | merge-chats [--dry-run] | events-token <instance[,instance...]|*> [ttl_secs]]";
+
+const DEFAULT_EVENTS_TOKEN_TTL_SECS: i64 = 3600;
+
+fn events_token(instances: &str, ttl_secs: i64) -> Result<Command, String> {
+    let instances: Vec<String> = instances
+        .split(',')
+        .map(str::trim)
+        .filter(|instance| !instance.is_empty())
+        .map(str::to_string)
+        .collect();
+    if instances.is_empty() {
+        return Err("events-token needs at least one instance".to_string());
+    }
+    if ttl_secs <= 0 {
+        return Err(format!("Invalid token ttl '{}'", ttl_secs));
+    }
+    Ok(Command::EventsToken { instances, ttl_secs })
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => Ok(Command::Consume),
+        ["migrate", "up"] => Ok(Command::Migrate(MigrateCommand::Up)),
+        ["migrate", "down"] => Ok(Command::Migrate(MigrateCommand::Down(1))),
+        ["migrate", "down", steps] => steps
+            .parse()
+            .map(|steps| Command::Migrate(MigrateCommand::Down(steps)))
+            .map_err(|_| format!("Invalid number of steps '{}'", steps)),
+        ["migrate", "status"] => Ok(Command::Migrate(MigrateCommand::Status)),
+        ["migrate", "redis"] => Ok(Command::MigrateRedis),
+        ["merge-chats"] => Ok(Command::MergeChats { dry_run: false }),
+        ["merge-chats", "--dry-run"] => Ok(Command::MergeChats { dry_run: true }),
+        ["events-token", instances] => events_token(instances, DEFAULT_EVENTS_TOKEN_TTL_SECS),
+        ["events-token", instances, ttl] => ttl
+            .parse()
+            .map_err(|_| format!("Invalid token ttl '{}'", ttl))
+            .and_then(|ttl_secs| events_token(instances, ttl_secs)),
+        _ => Err(format!("Unknown command '{}'", args.join(" "))),
+    }
+}
@@ -0,0 +1,27 @@
+use log::info;
+use crate::config::config::DotEnv;
+use crate::phone::normalize::PhoneNormalizer;
+use crate::redis_mod::merge::merge_duplicate_chats;
+use crate::redis_mod::redis::connect_redis;
+
+pub async fn run_merge_chats(env: &DotEnv, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let phone = PhoneNormalizer::from_config(&env.phone)?;
+    let mut redis_conn = connect_redis(&env.redis_url).await?;
+    let (report, merges) = merge_duplicate_chats(&mut redis_conn, &phone, dry_run).await?;
+
+    for merge in &merges {
+        println!("{} <- {} ({} message(s))", merge.target, merge.sources.join(", "), merge.messages);
+        for (field, value) in &merge.fields {
+            println!("    {:<16} {}", field, value);
+        }
+    }
+    if dry_run {
+        info!(
+            "Dry run: would merge {} chat(s) into {} and move {} message(s), nothing was changed",
+            report.chats, report.groups, report.messages
+        );
+    } else {
+        info!("Merged {} chat(s) into {} and moved {} message(s)", report.chats, report.groups, report.messages);
+    }
+    Ok(())
+}
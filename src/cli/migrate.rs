@@ -0,0 +1,48 @@
+use log::{error, info};
+use crate::cli::command::MigrateCommand;
+use crate::config::config::DotEnv;
+use crate::database::connect::create_pool;
+use crate::database::migrate::{migrate_down, migrate_up, migration_status};
+use crate::redis_mod::migrate::migrate_list_keys;
+use crate::redis_mod::redis::connect_redis;
+
+pub async fn run_migrate_redis(env: &DotEnv) -> Result<(), Box<dyn std::error::Error>> {
+    let mut redis_conn = connect_redis(&env.redis_url).await?;
+    let report = migrate_list_keys(&mut redis_conn).await?;
+    info!(
+        "Converted {} chat(s) and {} message(s) to the hash/sorted set layout, skipped {} malformed entries",
+        report.chats, report.messages, report.skipped
+    );
+    Ok(())
+}
+
+pub async fn run_migrate(env: &DotEnv, command: MigrateCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = create_pool(&env.db_url, &env.db)?;
+    let mut client = match pool.get().await {
+        Ok(client) => client,
+        Err(e) => {
+            error!("ERROR: Couldn't connect to Database: {}", e);
+            return Err(e.into());
+        }
+    };
+
+    match command {
+        MigrateCommand::Up => {
+            let applied = migrate_up(&mut client).await?;
+            info!("Applied {} migration(s)", applied);
+        }
+        MigrateCommand::Down(steps) => {
+            let reverted = migrate_down(&mut client, steps).await?;
+            info!("Reverted {} migration(s)", reverted);
+        }
+        MigrateCommand::Status => {
+            for migration in migration_status(&client).await? {
+                match migration.applied_at {
+                    Some(applied_at) => println!("{:>4}  {:<32} applied at {}", migration.version, migration.name, applied_at),
+                    None => println!("{:>4}  {:<32} pending", migration.version, migration.name),
+                }
+            }
+        }
+    }
+    Ok(())
+}
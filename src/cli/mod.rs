@@ -0,0 +1,4 @@
+pub mod command;
+pub mod migrate;
+pub mod merge;
+pub mod token;
@@ -0,0 +1,9 @@
+use chrono::Utc;
+use crate::config::config::DotEnv;
+use crate::events::token::sign;
+
+pub fn run_events_token(env: &DotEnv, instances: &[String], ttl_secs: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let secret = env.events.gateway_token.as_deref().ok_or("EVENTS_GATEWAY_TOKEN is not set")?;
+    println!("{}", sign(secret, instances, Utc::now().timestamp() + ttl_secs));
+    Ok(())
+}
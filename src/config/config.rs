@@ -1,11 +1,270 @@
 use dotenvy;
+use std::collections::HashMap;
 use std::env;
+use std::str::FromStr;
+use std::time::Duration;
 use log;
+use crate::parser::provider::Provider;
+use crate::process::policy::{IngestionConfig, IngestionPolicy};
+use crate::process::ratelimit::{RateLimitConfig, RateLimitPolicy};
+use crate::rabbit::retry::RetryPolicy;
+
+const DEFAULT_CONSUMER_QUEUES: &str = "outgoing_requests=outgoing,incoming_requests=incoming,evolution.messages.upsert=incoming,evolution.send.message=send_message,evolution.messages.update=status";
 
 pub struct DotEnv {
     pub rabbit_url: String,
     pub db_url: String,
+    pub db: DatabaseConfig,
     pub redis_url: String,
+    pub accept_legacy_payloads: bool,
+    pub shutdown_timeout: Duration,
+    pub queues: Vec<QueueConfig>,
+    pub media: MediaConfig,
+    pub auto_migrate: bool,
+    pub dedup_ttl: Duration,
+    pub dedup_processing_ttl: Duration,
+    pub events: EventsConfig,
+    pub phone: PhoneConfig,
+    pub ingestion: IngestionConfig,
+    pub rate_limits: RateLimitConfig,
+    pub http: HttpConfig,
+    pub results: RequestResultsConfig,
+    pub providers: ProviderSelection,
+    pub evolution: ApiEndpoints,
+    pub wuzapi: ApiEndpoints,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EndpointConfig {
+    pub url: Option<String>,
+    pub credential: Option<String>,
+}
+
+// Base URL and credential (Evolution apikey, Wuzapi user token) of a provider API, with per-instance overrides.
+pub struct ApiEndpoints {
+    pub default: EndpointConfig,
+    // Keyed by the env key of the instance (see `queue_env_key`).
+    pub instances: HashMap<String, EndpointConfig>,
+}
+
+impl ApiEndpoints {
+    pub fn resolve(&self, instance: &str) -> Option<(String, String)> {
+        let overrides = self.instances.get(&queue_env_key(instance)).cloned().unwrap_or_default();
+        let url = overrides.url.or_else(|| self.default.url.clone())?;
+        let credential = overrides.credential.or_else(|| self.default.credential.clone())?;
+        Some((url, credential))
+    }
+}
+
+pub struct ProviderSelection {
+    pub default: Provider,
+    pub instances: HashMap<String, Provider>,
+}
+
+impl ProviderSelection {
+    pub fn for_instance(&self, instance: &str) -> Provider {
+        self.instances.get(&queue_env_key(instance)).copied().unwrap_or(self.default)
+    }
+}
+
+pub struct RequestResultsConfig {
+    pub exchange: Option<String>,
+    pub routing_key: String,
+    pub ttl: Option<Duration>,
+}
+
+pub struct HttpConfig {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+}
+
+pub struct PhoneConfig {
+    pub default_country: Option<String>,
+    pub rules: Vec<String>,
+}
+
+pub struct EventsConfig {
+    pub gateway_addr: Option<String>,
+    pub gateway_token: Option<String>,
+    pub gateway_allow_origin: Option<String>,
+}
+
+pub struct DatabaseConfig {
+    pub pool_size: usize,
+    pub statement_timeout: Duration,
+    pub connect_timeout: Duration,
+    pub wait_timeout: Duration,
+    pub ssl_root_cert: Option<String>,
+}
+
+pub struct MediaConfig {
+    pub storage: String,
+    pub dir: String,
+    pub public_url: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_region: String,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+    pub fetch_from_evolution: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ConsumerOptions {
+    pub prefetch: u16,
+    pub concurrency: usize,
+    pub retry: RetryPolicy,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueueConfig {
+    pub name: String,
+    pub handler: String,
+    pub options: ConsumerOptions,
+}
+
+pub fn queue_env_key(queue_name: &str) -> String {
+    queue_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+fn parse_flag(name: &str, default: bool) -> bool {
+    env::var(name)
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(default)
+}
+
+fn parse_var<T: FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn retry_policy_for(defaults: &RetryPolicy, queue_name: &str) -> RetryPolicy {
+    let key = queue_env_key(queue_name);
+    RetryPolicy {
+        max_attempts: parse_var(&format!("{}_RETRY_MAX_ATTEMPTS", key), defaults.max_attempts),
+        base_delay: Duration::from_millis(parse_var(&format!("{}_RETRY_BASE_DELAY_MS", key), defaults.base_delay.as_millis() as u64)),
+        max_delay: Duration::from_millis(parse_var(&format!("{}_RETRY_MAX_DELAY_MS", key), defaults.max_delay.as_millis() as u64)),
+        jitter: Duration::from_millis(parse_var(&format!("{}_RETRY_JITTER_MS", key), defaults.jitter.as_millis() as u64)),
+    }
+}
+
+fn consumer_options_for(queue_name: &str, defaults: &ConsumerDefaults) -> ConsumerOptions {
+    let key = queue_env_key(queue_name);
+    let prefetch = parse_var(&format!("{}_PREFETCH", key), defaults.prefetch).max(1);
+    let concurrency = env::var(format!("{}_CONCURRENCY", key))
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .or(defaults.concurrency)
+        .unwrap_or(prefetch as usize);
+    if concurrency > prefetch as usize {
+        log::warn!("Concurrency {} for {} exceeds prefetch {}, capping it", concurrency, queue_name, prefetch);
+    }
+
+    ConsumerOptions {
+        prefetch,
+        concurrency: concurrency.clamp(1, prefetch as usize),
+        retry: retry_policy_for(&defaults.retry, queue_name),
+    }
+}
+
+struct ConsumerDefaults {
+    prefetch: u16,
+    concurrency: Option<usize>,
+    retry: RetryPolicy,
+}
+
+fn parse_queues(spec: &str, defaults: &ConsumerDefaults) -> Result<Vec<QueueConfig>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, handler) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid CONSUMER_QUEUES entry '{}', expected queue=handler", entry))?;
+            let (name, handler) = (name.trim(), handler.trim());
+            Ok(QueueConfig {
+                name: name.to_string(),
+                handler: handler.to_string(),
+                options: consumer_options_for(name, defaults),
+            })
+        })
+        .collect()
+}
+
+fn parse_endpoints(url_var: &str, credential_var: &str) -> ApiEndpoints {
+    let url_prefix = format!("{}_", url_var);
+    let credential_prefix = format!("{}_", credential_var);
+    let mut instances: HashMap<String, EndpointConfig> = HashMap::new();
+    for (name, value) in env::vars().filter(|(_, value)| !value.is_empty()) {
+        if let Some(instance) = name.strip_prefix(&url_prefix) {
+            instances.entry(instance.to_string()).or_default().url = Some(value);
+        } else if let Some(instance) = name.strip_prefix(&credential_prefix) {
+            instances.entry(instance.to_string()).or_default().credential = Some(value);
+        }
+    }
+    ApiEndpoints {
+        default: EndpointConfig {
+            url: env::var(url_var).ok().filter(|v| !v.is_empty()),
+            credential: env::var(credential_var).ok().filter(|v| !v.is_empty()),
+        },
+        instances,
+    }
+}
+
+const WHATSAPP_PROVIDER_PREFIX: &str = "WHATSAPP_PROVIDER_";
+
+fn parse_providers() -> Result<ProviderSelection, String> {
+    let parse = |name: &str, value: &str| {
+        Provider::from_name(value).ok_or_else(|| format!("Unknown provider '{}' in {}, expected evolution or wuzapi", value, name))
+    };
+    let default = match env::var("WHATSAPP_PROVIDER") {
+        Ok(value) => parse("WHATSAPP_PROVIDER", &value)?,
+        Err(_) => Provider::Evolution,
+    };
+    let mut instances = HashMap::new();
+    for (name, value) in env::vars() {
+        if let Some(instance) = name.strip_prefix(WHATSAPP_PROVIDER_PREFIX) {
+            instances.insert(instance.to_string(), parse(&name, &value)?);
+        }
+    }
+    Ok(ProviderSelection { default, instances })
+}
+
+const INGEST_POLICY_PREFIX: &str = "INGEST_POLICY_";
+
+fn parse_ingestion() -> Result<IngestionConfig, String> {
+    let default = IngestionPolicy::default().with_spec(&env::var("INGEST_POLICY").unwrap_or_default())?;
+    let mut instances = HashMap::new();
+    for (name, spec) in env::vars() {
+        if let Some(instance) = name.strip_prefix(INGEST_POLICY_PREFIX) {
+            let policy = default
+                .with_spec(&spec)
+                .map_err(|e| format!("{} in {}", e, name))?;
+            instances.insert(instance.to_string(), policy);
+        }
+    }
+    Ok(IngestionConfig { default, instances })
+}
+
+const RATE_LIMIT_PREFIX: &str = "RATE_LIMIT_";
+
+fn parse_rate_limits() -> Result<RateLimitConfig, String> {
+    let default = RateLimitPolicy::default().with_spec(&env::var("RATE_LIMIT").unwrap_or_default())?;
+    let mut instances = HashMap::new();
+    for (name, spec) in env::vars() {
+        if let Some(instance) = name.strip_prefix(RATE_LIMIT_PREFIX) {
+            let policy = default
+                .with_spec(&spec)
+                .map_err(|e| format!("{} in {}", e, name))?;
+            instances.insert(instance.to_string(), policy);
+        }
+    }
+    Ok(RateLimitConfig { default, instances })
 }
 
 pub fn load_dotenv() -> Result<DotEnv, Box<dyn std::error::Error>> {
@@ -17,12 +276,93 @@ pub fn load_dotenv() -> Result<DotEnv, Box<dyn std::error::Error>> {
         .map_err(|e| format!("Failed to get DB_URL: {}", e))?;
     let redis_url = env::var("REDIS_URL")
         .map_err(|e| format!("Failed to get REDIS_URL: {}", e))?;
+    let db = DatabaseConfig {
+        pool_size: parse_var("DB_POOL_SIZE", 16),
+        statement_timeout: Duration::from_millis(parse_var("DB_STATEMENT_TIMEOUT_MS", 30_000)),
+        connect_timeout: Duration::from_secs(parse_var("DB_CONNECT_TIMEOUT_SECS", 10)),
+        wait_timeout: Duration::from_secs(parse_var("DB_POOL_WAIT_TIMEOUT_SECS", 30)),
+        ssl_root_cert: env::var("DB_SSL_ROOT_CERT").ok(),
+    };
+    let accept_legacy_payloads = parse_flag("ACCEPT_LEGACY_PAYLOADS", true);
+    let defaults = ConsumerDefaults {
+        prefetch: parse_var("PREFETCH_COUNT", 10),
+        concurrency: env::var("CONCURRENCY").ok().and_then(|v| v.parse().ok()),
+        retry: RetryPolicy {
+            max_attempts: parse_var("RETRY_MAX_ATTEMPTS", 5),
+            base_delay: Duration::from_millis(parse_var("RETRY_BASE_DELAY_MS", 1000)),
+            max_delay: Duration::from_millis(parse_var("RETRY_MAX_DELAY_MS", 300_000)),
+            jitter: Duration::from_millis(parse_var("RETRY_JITTER_MS", 500)),
+        },
+    };
+    let queues = parse_queues(
+        &env::var("CONSUMER_QUEUES").unwrap_or_else(|_| DEFAULT_CONSUMER_QUEUES.to_string()),
+        &defaults,
+    )?;
+    let shutdown_timeout = Duration::from_secs(parse_var("SHUTDOWN_TIMEOUT_SECS", 30));
+    let events = EventsConfig {
+        gateway_addr: env::var("EVENTS_GATEWAY_ADDR").ok().filter(|v| !v.is_empty()),
+        gateway_token: env::var("EVENTS_GATEWAY_TOKEN").ok().filter(|v| !v.is_empty()),
+        gateway_allow_origin: env::var("EVENTS_GATEWAY_ALLOW_ORIGIN").ok(),
+    };
+    if events.gateway_addr.is_some() && events.gateway_token.is_none() {
+        return Err("EVENTS_GATEWAY_TOKEN is required when EVENTS_GATEWAY_ADDR is set".into());
+    }
+    let media = MediaConfig {
+        storage: env::var("MEDIA_STORAGE").unwrap_or_else(|_| "inline".to_string()).to_ascii_lowercase(),
+        dir: env::var("MEDIA_DIR").unwrap_or_else(|_| "./media".to_string()),
+        public_url: env::var("MEDIA_PUBLIC_URL").ok(),
+        s3_bucket: env::var("MEDIA_S3_BUCKET").ok(),
+        s3_endpoint: env::var("MEDIA_S3_ENDPOINT").ok(),
+        s3_region: env::var("MEDIA_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+        s3_access_key: env::var("MEDIA_S3_ACCESS_KEY").ok(),
+        s3_secret_key: env::var("MEDIA_S3_SECRET_KEY").ok(),
+        fetch_from_evolution: parse_flag("MEDIA_FETCH_FROM_EVOLUTION", false),
+    };
+    let ingestion = parse_ingestion()?;
+    let rate_limits = parse_rate_limits()?;
+    let phone = PhoneConfig {
+        default_country: Some(env::var("PHONE_DEFAULT_COUNTRY").unwrap_or_else(|_| "55".to_string()))
+            .map(|code| code.trim().trim_start_matches('+').to_string())
+            .filter(|code| !code.is_empty()),
+        rules: env::var("PHONE_COUNTRY_RULES")
+            .unwrap_or_else(|_| "br".to_string())
+            .split(',')
+            .map(|rule| rule.trim().to_ascii_lowercase())
+            .filter(|rule| !rule.is_empty())
+            .collect(),
+    };
     
     log::info!(".ENV Vars loaded successfully! Returning them now...");
 
     Ok(DotEnv {
         rabbit_url,
         db_url,
-        redis_url
+        db,
+        redis_url,
+        accept_legacy_payloads,
+        shutdown_timeout,
+        queues,
+        media,
+        auto_migrate: parse_flag("AUTO_MIGRATE", false),
+        dedup_ttl: Duration::from_secs(parse_var("DEDUP_TTL_SECS", 604_800)),
+        dedup_processing_ttl: Duration::from_secs(parse_var("DEDUP_PROCESSING_TTL_SECS", 300)),
+        events,
+        phone,
+        ingestion,
+        rate_limits,
+        providers: parse_providers()?,
+        evolution: parse_endpoints("EVOLUTION_URL", "EVOLUTION_APIKEY"),
+        wuzapi: parse_endpoints("WUZAPI_URL", "WUZAPI_TOKEN"),
+        http: HttpConfig {
+            connect_timeout: Duration::from_secs(parse_var("HTTP_CONNECT_TIMEOUT_SECS", 10)),
+            read_timeout: Duration::from_secs(parse_var("HTTP_READ_TIMEOUT_SECS", 30)),
+        },
+        results: RequestResultsConfig {
+            exchange: env::var("REQUEST_RESULT_EXCHANGE").ok().filter(|v| !v.is_empty()),
+            routing_key: env::var("REQUEST_RESULT_ROUTING_KEY").unwrap_or_else(|_| "request.result".to_string()),
+            ttl: Some(parse_var("REQUEST_RESULT_TTL_SECS", 0))
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        },
     })
-}
\ No newline at end of file
+}
@@ -1,12 +1,47 @@
-use tokio_postgres::{Client, Error};
-use log::error;
-
-pub async fn connect_db(db_url: &str) -> Result<Client, Error> {
-    let (client, connection) = tokio_postgres::connect(db_url, tokio_postgres::NoTls).await?;
-    tokio::spawn( async move {
-        if let Err(e) = connection.await {
-            error!("Database connection failed: {}", e);
+use deadpool_postgres::{Manager, ManagerConfig, Pool, RecyclingMethod, Runtime};
+use tokio_postgres::NoTls;
+use log::info;
+use crate::config::config::DatabaseConfig;
+use crate::database::tls::{extract_param, make_tls, SslMode};
+
+pub fn create_pool(db_url: &str, config: &DatabaseConfig) -> Result<Pool, String> {
+    let (db_url, ssl_mode) = extract_param(db_url, "sslmode");
+    let (db_url, url_root_cert) = extract_param(&db_url, "sslrootcert");
+    let ssl_mode = ssl_mode.as_deref().map(SslMode::from_name).transpose()?;
+    let root_cert = config.ssl_root_cert.as_deref().or(url_root_cert.as_deref());
+
+    let mut pg_config: tokio_postgres::Config = db_url
+        .parse()
+        .map_err(|e| format!("Invalid DB_URL: {}", e))?;
+    pg_config.connect_timeout(config.connect_timeout);
+    let statement_timeout = format!("-c statement_timeout={}", config.statement_timeout.as_millis());
+    let options = match pg_config.get_options() {
+        Some(existing) => format!("{} {}", existing, statement_timeout),
+        None => statement_timeout,
+    };
+    pg_config.options(&options);
+
+    let manager_config = ManagerConfig {
+        recycling_method: RecyclingMethod::Verified,
+    };
+    let manager = match ssl_mode {
+        None | Some(SslMode::Disable) => Manager::from_config(pg_config, NoTls, manager_config),
+        Some(mode) => {
+            pg_config.ssl_mode(match mode {
+                SslMode::Prefer => tokio_postgres::config::SslMode::Prefer,
+                _ => tokio_postgres::config::SslMode::Require,
+            });
+            info!("Connecting to Database over TLS (sslmode={:?})", mode);
+            Manager::from_config(pg_config, make_tls(mode, root_cert)?, manager_config)
         }
-    });
-    Ok(client)
-}
\ No newline at end of file
+    };
+
+    Pool::builder(manager)
+        .max_size(config.pool_size)
+        .wait_timeout(Some(config.wait_timeout))
+        .create_timeout(Some(config.connect_timeout))
+        .recycle_timeout(Some(config.connect_timeout))
+        .runtime(Runtime::Tokio1)
+        .build()
+        .map_err(|e| format!("Couldn't build Database pool: {}", e))
+}
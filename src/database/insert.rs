@@ -1,5 +1,7 @@
 use tokio_postgres::{Client, Error};
-use crate::parser::library::{Chat, Message, Customer};
+use crate::parser::content::parse_timestamp;
+use crate::parser::library::{Chat, Message, Customer, IncomingMessage};
+use crate::parser::status::StatusUpdate;
 use log::error;
 
 pub async fn upsert_chats(client:&Client, chat: &Chat) -> Result<(), Error> {
@@ -30,7 +32,7 @@ pub async fn upsert_chats(client:&Client, chat: &Chat) -> Result<(), Error> {
 
 pub async fn upsert_messages(client: &Client, msg: &Message) -> Result<(), Error> {
     match client.execute(
-        "INSERT INTO messages (id, from, to, text, delivered, chat_id) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO UPDATE SET from = $2, to = $3, text = $4, delivered = $5, chat_id = $6",
+        "INSERT INTO messages (id, \"from\", \"to\", text, delivered, chat_id) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO UPDATE SET \"from\" = $2, \"to\" = $3, text = $4, delivered = $5, chat_id = $6",
         &[&msg.id, &msg.from, &msg.to, &msg.text, &msg.delivered, &msg.chat_id]
     ).await {
         Ok(_) => Ok(()),
@@ -65,4 +67,54 @@ pub async fn upsert_customer(client: &Client, customer: &Customer) -> Result<(),
             }
         }
     }
-}   
\ No newline at end of file
+}
+
+pub async fn upsert_wa_message(client: &Client, chat_id: &str, record: &IncomingMessage) -> Result<(), Error> {
+    let message = &record.message;
+    let direction = if record.from_me { "outgoing" } else { "incoming" };
+    let body = Some(&message.body).filter(|body| !body.is_empty() && !body.starts_with("data:"));
+    let quoted_id = message.quoted.as_ref().map(|quoted| &quoted.id);
+    let media = message.media.as_ref();
+    let media_size = media.map(|media| media.size as i64);
+    let sent_at = parse_timestamp(&message.timestamp);
+    match client.execute(
+        "INSERT INTO wa_messages (instance_id, wa_message_id, chat_id, direction, message_type, \"from\", \"to\", text, body, caption, mime_type, file_name, quoted_id, media_key, media_url, media_size, media_sha256, sent_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18) \
+         ON CONFLICT (instance_id, wa_message_id) DO UPDATE SET chat_id = $3, direction = $4, message_type = $5, \"from\" = $6, \"to\" = $7, text = $8, body = $9, caption = $10, mime_type = $11, file_name = $12, quoted_id = $13, \
+         media_key = COALESCE($14, wa_messages.media_key), media_url = COALESCE($15, wa_messages.media_url), media_size = COALESCE($16, wa_messages.media_size), media_sha256 = COALESCE($17, wa_messages.media_sha256), sent_at = COALESCE($18, wa_messages.sent_at)",
+        &[
+            &record.instance_id, &record.message_id, &chat_id, &direction, &message.msg_type, &message.from, &message.to, &message.text,
+            &body, &message.caption, &message.mime_type, &message.file_name, &quoted_id,
+            &media.map(|media| &media.key), &media.map(|media| &media.url), &media_size, &media.map(|media| &media.sha256), &sent_at,
+        ]
+    ).await {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("Error: Failed upsert on wa_messages table: {}", e);
+            Err(e)
+        }
+    }
+}
+
+pub async fn record_wa_message_status(client: &Client, update: &StatusUpdate) -> Result<u64, Error> {
+    let status = update.status.as_str();
+    if let Err(e) = client.execute(
+        "INSERT INTO wa_message_statuses (instance_id, wa_message_id, status, occurred_at) VALUES ($4, $1, $2, $3) \
+         ON CONFLICT (instance_id, wa_message_id, status) DO UPDATE SET occurred_at = LEAST(wa_message_statuses.occurred_at, $3)",
+        &[&update.message_id, &status, &update.timestamp, &update.instance_id]
+    ).await {
+        error!("Error: Failed insert on wa_message_statuses table: {}", e);
+        return Err(e);
+    }
+    match client.execute(
+        "UPDATE wa_messages SET status = $2, status_at = $3 \
+         WHERE wa_message_id = $1 AND ($5 = '' OR instance_id = $5) AND (status IS NULL OR status = ANY($4))",
+        &[&update.message_id, &status, &update.timestamp, &update.status.preceding(), &update.instance_id]
+    ).await {
+        Ok(updated) => Ok(updated),
+        Err(e) => {
+            error!("Error: Failed status update on wa_messages table: {}", e);
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use tokio_postgres::{Client, Error};
+use log::{info, warn};
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+macro_rules! migration {
+    ($version:expr, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            up: include_str!(concat!("../../migrations/", $name, ".up.sql")),
+            down: include_str!(concat!("../../migrations/", $name, ".down.sql")),
+        }
+    };
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_create_customers"),
+    migration!(2, "0002_create_chats"),
+    migration!(3, "0003_create_messages"),
+    migration!(4, "0004_create_wa_messages"),
+    migration!(5, "0005_add_wa_message_status"),
+    migration!(6, "0006_scope_wa_message_statuses_by_instance"),
+];
+
+// The first migrations adopt tables that production databases had before the migration tool existed,
+// so reverting them would drop that data; `migrate down` stops above this version.
+const BASELINE_VERSION: i64 = 3;
+
+// Arbitrary key shared by every consumer replica so only one of them migrates at a time.
+const MIGRATION_LOCK_KEY: i64 = 0x0077_6173_6f6c;
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    pub applied_at: Option<String>,
+}
+
+async fn ensure_migrations_table(client: &Client) -> Result<(), Error> {
+    client.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        )"
+    ).await
+}
+
+async fn applied_versions(client: &Client) -> Result<Vec<(i64, String)>, Error> {
+    let rows = client.query(
+        "SELECT version, applied_at::text FROM schema_migrations ORDER BY version",
+        &[],
+    ).await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+pub async fn migrate_up(client: &mut Client) -> Result<usize, Error> {
+    ensure_migrations_table(client).await?;
+    client.execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY]).await?;
+    let result = apply_pending(client).await;
+    client.execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY]).await?;
+    result
+}
+
+async fn apply_pending(client: &mut Client) -> Result<usize, Error> {
+    let applied: Vec<i64> = applied_versions(client).await?.into_iter().map(|(v, _)| v).collect();
+    let mut count = 0;
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        info!("Applying migration {}", migration.name);
+        let tx = client.transaction().await?;
+        tx.batch_execute(migration.up).await?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+            &[&migration.version, &migration.name],
+        ).await?;
+        tx.commit().await?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub async fn migrate_down(client: &mut Client, steps: usize) -> Result<usize, Error> {
+    ensure_migrations_table(client).await?;
+    client.execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY]).await?;
+    let result = revert_latest(client, steps).await;
+    client.execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY]).await?;
+    result
+}
+
+async fn revert_latest(client: &mut Client, steps: usize) -> Result<usize, Error> {
+    let applied = applied_versions(client).await?;
+    let mut count = 0;
+    for (version, _) in applied.iter().rev().take(steps) {
+        let Some(migration) = MIGRATIONS.iter().find(|m| m.version == *version) else {
+            warn!("Migration {} is recorded in the database but not embedded in this binary, stopping", version);
+            break;
+        };
+        if migration.version <= BASELINE_VERSION {
+            warn!("Migration {} is part of the baseline schema and can't be reverted, stopping", migration.name);
+            break;
+        }
+        info!("Reverting migration {}", migration.name);
+        let tx = client.transaction().await?;
+        tx.batch_execute(migration.down).await?;
+        tx.execute("DELETE FROM schema_migrations WHERE version = $1", &[&migration.version]).await?;
+        tx.commit().await?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+pub async fn migration_status(client: &Client) -> Result<Vec<MigrationStatus>, Error> {
+    ensure_migrations_table(client).await?;
+    let applied = applied_versions(client).await?;
+    Ok(MIGRATIONS.iter().map(|m| MigrationStatus {
+        version: m.version,
+        name: m.name,
+        applied_at: applied.iter().find(|(v, _)| *v == m.version).map(|(_, at)| at.clone()),
+    }).collect())
+}
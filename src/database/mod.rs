@@ -1,2 +1,4 @@
 pub mod connect;
-pub mod insert;
\ No newline at end of file
+pub mod tls;
+pub mod insert;
+pub mod migrate;
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, ClientConfig, DigitallySignedStruct, Error, RootCertStore, SignatureScheme};
+use tokio_postgres_rustls::MakeRustlsConnect;
+use log::warn;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SslMode {
+    Disable,
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+impl SslMode {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "disable" => Ok(SslMode::Disable),
+            "prefer" | "allow" => Ok(SslMode::Prefer),
+            "require" => Ok(SslMode::Require),
+            "verify-ca" => Ok(SslMode::VerifyCa),
+            "verify-full" => Ok(SslMode::VerifyFull),
+            other => Err(format!("Unsupported sslmode '{}'", other)),
+        }
+    }
+}
+
+// tokio-postgres only understands disable/prefer/require, so TLS-related parameters are
+// pulled out of DB_URL (URL or key=value form) before handing it over.
+pub fn extract_param(db_url: &str, name: &str) -> (String, Option<String>) {
+    let prefix = format!("{}=", name);
+    if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+        let Some((base, query)) = db_url.split_once('?') else {
+            return (db_url.to_string(), None);
+        };
+        let mut value = None;
+        let rest: Vec<&str> = query
+            .split('&')
+            .filter(|param| match param.strip_prefix(&prefix) {
+                Some(v) => {
+                    value = Some(v.to_string());
+                    false
+                }
+                None => true,
+            })
+            .collect();
+        let url = if rest.is_empty() { base.to_string() } else { format!("{}?{}", base, rest.join("&")) };
+        (url, value)
+    } else {
+        let mut value = None;
+        let rest: Vec<&str> = db_url
+            .split_whitespace()
+            .filter(|param| match param.strip_prefix(&prefix) {
+                Some(v) => {
+                    value = Some(v.trim_matches('\'').to_string());
+                    false
+                }
+                None => true,
+            })
+            .collect();
+        (rest.join(" "), value)
+    }
+}
+
+#[derive(Debug)]
+struct RelaxedVerifier {
+    chain: Option<Arc<WebPkiServerVerifier>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for RelaxedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        let Some(chain) = &self.chain else {
+            return Ok(ServerCertVerified::assertion());
+        };
+        match chain.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now) {
+            Err(Error::InvalidCertificate(CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. })) => {
+                Ok(ServerCertVerified::assertion())
+            }
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn root_store(root_cert: Option<&str>) -> Result<RootCertStore, String> {
+    let mut roots = RootCertStore::empty();
+    match root_cert {
+        Some(path) => {
+            for cert in CertificateDer::pem_file_iter(path).map_err(|e| format!("Couldn't read {}: {}", path, e))? {
+                let cert = cert.map_err(|e| format!("Invalid certificate in {}: {}", path, e))?;
+                roots.add(cert).map_err(|e| format!("Invalid certificate in {}: {}", path, e))?;
+            }
+        }
+        None => {
+            let native = rustls_native_certs::load_native_certs();
+            for e in &native.errors {
+                warn!("Couldn't load a system root certificate: {}", e);
+            }
+            let (added, _) = roots.add_parsable_certificates(native.certs);
+            if added == 0 {
+                return Err("No system root certificates found, set DB_SSL_ROOT_CERT".to_string());
+            }
+        }
+    }
+    Ok(roots)
+}
+
+pub fn make_tls(mode: SslMode, root_cert: Option<&str>) -> Result<MakeRustlsConnect, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("Couldn't configure TLS: {}", e))?;
+
+    let config = match mode {
+        SslMode::VerifyFull => builder
+            .with_root_certificates(root_store(root_cert)?)
+            .with_no_client_auth(),
+        SslMode::VerifyCa => {
+            let chain = WebPkiServerVerifier::builder_with_provider(Arc::new(root_store(root_cert)?), provider.clone())
+                .build()
+                .map_err(|e| format!("Couldn't configure TLS: {}", e))?;
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(RelaxedVerifier { chain: Some(chain), provider }))
+                .with_no_client_auth()
+        }
+        SslMode::Disable | SslMode::Prefer | SslMode::Require => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(RelaxedVerifier { chain: None, provider }))
+            .with_no_client_auth(),
+    };
+    Ok(MakeRustlsConnect::new(config))
+}
@@ -0,0 +1,152 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use axum::extract::{Path, Query, State};
+use axum::http::header::{ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use chrono::Utc;
+use futures::StreamExt;
+use redis::AsyncCommands;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+use log::{error, info, warn};
+use crate::config::config::EventsConfig;
+use crate::events::publish::{chat_channel, instance_channel};
+use crate::events::token::{verify, EventsScope};
+use crate::redis_mod::redis::chat_key;
+
+#[derive(Clone)]
+struct GatewayState {
+    redis: redis::Client,
+    secret: Arc<String>,
+    allow_origin: Option<HeaderValue>,
+    shutdown: CancellationToken,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+// EventSource can't send headers, so browsers pass the token as a query parameter instead. Query
+// strings end up in proxy logs, which is why tokens are scoped and short-lived.
+fn authorize(state: &GatewayState, headers: &HeaderMap, query: &TokenQuery) -> Option<EventsScope> {
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or(query.token.as_deref())?;
+    verify(&state.secret, provided, Utc::now().timestamp())
+}
+
+async fn chat_instance(state: &GatewayState, chat_id: &str) -> redis::RedisResult<String> {
+    let mut redis_conn = state.redis.get_multiplexed_async_connection().await?;
+    let instance_id: Option<String> = redis_conn.hget(chat_key(chat_id), "instance_id").await?;
+    Ok(instance_id.unwrap_or_default())
+}
+
+async fn subscribe(state: GatewayState, channel: String) -> Response {
+    let mut pubsub = match state.redis.get_async_pubsub().await {
+        Ok(pubsub) => pubsub,
+        Err(e) => {
+            error!("Couldn't open Redis pub/sub connection: {}", e);
+            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        }
+    };
+    if let Err(e) = pubsub.subscribe(&channel).await {
+        error!("Couldn't subscribe to {}: {}", channel, e);
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+    info!("New events subscriber on {}", channel);
+
+    let stream = pubsub
+        .into_on_message()
+        .map(|msg| {
+            let payload: String = msg.get_payload().unwrap_or_default();
+            let name = serde_json::from_str::<Value>(&payload)
+                .ok()
+                .and_then(|event| event.get("event").and_then(|v| v.as_str()).map(str::to_string))
+                .unwrap_or_else(|| "message".to_string());
+            Ok::<_, Infallible>(Event::default().event(name).data(payload))
+        })
+        .take_until(state.shutdown.clone().cancelled_owned());
+
+    let mut response = Sse::new(stream).keep_alive(KeepAlive::default()).into_response();
+    if let Some(origin) = state.allow_origin {
+        response.headers_mut().insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    }
+    response
+}
+
+async fn instance_events(
+    State(state): State<GatewayState>,
+    Path(instance_id): Path<String>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(scope) = authorize(&state, &headers, &query) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    if !scope.allows(&instance_id) {
+        warn!("Events token doesn't cover instance {}", instance_id);
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    subscribe(state, instance_channel(&instance_id)).await
+}
+
+async fn chat_events(
+    State(state): State<GatewayState>,
+    Path(chat_id): Path<String>,
+    Query(query): Query<TokenQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(scope) = authorize(&state, &headers, &query) else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let instance_id = match chat_instance(&state, &chat_id).await {
+        Ok(instance_id) => instance_id,
+        Err(e) => {
+            error!("Couldn't look up the instance of chat {}: {}", chat_id, e);
+            return StatusCode::SERVICE_UNAVAILABLE.into_response();
+        }
+    };
+    // Chats without a known instance are only visible to tokens covering every instance.
+    if !scope.allows(&instance_id) {
+        warn!("Events token doesn't cover chat {}", chat_id);
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    subscribe(state, chat_channel(&chat_id)).await
+}
+
+pub async fn run_gateway(
+    config: &EventsConfig,
+    redis_url: &str,
+    shutdown: CancellationToken,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (Some(addr), Some(token)) = (&config.gateway_addr, &config.gateway_token) else {
+        return Ok(());
+    };
+    let state = GatewayState {
+        redis: redis::Client::open(redis_url)?,
+        secret: Arc::new(token.clone()),
+        allow_origin: config.gateway_allow_origin.as_deref().map(HeaderValue::from_str).transpose()?,
+        shutdown: shutdown.clone(),
+    };
+    let app = Router::new()
+        .route("/health", get(|| async { "ok" }))
+        .route("/events/instances/{instance_id}", get(instance_events))
+        .route("/events/chats/{chat_id}", get(chat_events))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Events gateway listening on {}", addr);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.cancelled().await })
+        .await?;
+    info!("Events gateway stopped");
+    Ok(())
+}
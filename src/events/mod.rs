@@ -0,0 +1,3 @@
+pub mod publish;
+pub mod gateway;
+pub mod token;
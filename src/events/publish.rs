@@ -0,0 +1,83 @@
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde_json::{json, Value};
+use log::{debug, error};
+use crate::parser::library::NormalizedMessage;
+use crate::parser::status::StatusUpdate;
+
+pub fn instance_channel(instance_id: &str) -> String {
+    format!("events:instance:{}", instance_id)
+}
+
+pub fn chat_channel(chat_id: &str) -> String {
+    format!("events:chat:{}", chat_id)
+}
+
+async fn publish(redis_conn: &mut ConnectionManager, channels: &[String], event: &Value) {
+    let payload = event.to_string();
+    for channel in channels {
+        match redis_conn.publish::<_, _, i64>(channel, &payload).await {
+            Ok(receivers) => debug!("Published {} to {} subscriber(s) of {}", event["event"], receivers, channel),
+            Err(e) => error!("Failed to publish {} on {}: {}", event["event"], channel, e),
+        }
+    }
+}
+
+pub async fn publish_message(
+    redis_conn: &mut ConnectionManager,
+    chat_id: &str,
+    instance_id: &str,
+    message: &NormalizedMessage,
+    from_me: bool,
+    chat_created: bool,
+) {
+    let mut channels = vec![chat_channel(chat_id)];
+    if !instance_id.is_empty() {
+        channels.push(instance_channel(instance_id));
+    }
+
+    if chat_created && !instance_id.is_empty() {
+        let event = json!({
+            "event": "chat.created",
+            "chat_id": chat_id,
+            "instance_id": instance_id,
+            "timestamp": Utc::now().timestamp_millis(),
+        });
+        publish(redis_conn, &channels[1..], &event).await;
+    }
+
+    let event = json!({
+        "event": "message.created",
+        "chat_id": chat_id,
+        "instance_id": instance_id,
+        "message_id": message.id,
+        "type": message.msg_type,
+        "preview": message.text.chars().take(120).collect::<String>(),
+        "direction": if from_me { "outgoing" } else { "incoming" },
+        "timestamp": message.timestamp,
+    });
+    publish(redis_conn, &channels, &event).await;
+}
+
+// Fanned out like message events, so instance dashboards also see deliveries and reads. Some receipts
+// don't say which chat they belong to; those only reach the instance channel.
+pub async fn publish_status(redis_conn: &mut ConnectionManager, chat_id: &str, message_id: &str, update: &StatusUpdate) {
+    let mut channels = Vec::new();
+    if !chat_id.is_empty() {
+        channels.push(chat_channel(chat_id));
+    }
+    if !update.instance_id.is_empty() {
+        channels.push(instance_channel(&update.instance_id));
+    }
+
+    let event = json!({
+        "event": "message.status",
+        "chat_id": chat_id,
+        "instance_id": update.instance_id,
+        "message_id": message_id,
+        "status": update.status.as_str(),
+        "timestamp": update.timestamp.timestamp_millis(),
+    });
+    publish(redis_conn, &channels, &event).await;
+}
@@ -0,0 +1,56 @@
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const ALL_INSTANCES: &str = "*";
+
+// A gateway token is `<instances>.<expires_at>.<signature>`: a comma-separated list of instance
+// names (or `*` for all of them), a unix timestamp and the hex HMAC-SHA256 of the first two parts,
+// keyed by EVENTS_GATEWAY_TOKEN. The CRM backend signs one per user, so a leaked token only exposes
+// its own instances and only until it expires.
+pub struct EventsScope {
+    instances: Vec<String>,
+}
+
+impl EventsScope {
+    pub fn allows(&self, instance_id: &str) -> bool {
+        self.instances.iter().any(|allowed| allowed == ALL_INSTANCES || (!instance_id.is_empty() && allowed == instance_id))
+    }
+}
+
+fn signature(secret: &str, claims: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(claims.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+pub fn sign(secret: &str, instances: &[String], expires_at: i64) -> String {
+    let claims = format!("{}.{}", instances.join(","), expires_at);
+    let signature = signature(secret, &claims);
+    format!("{}.{}", claims, signature)
+}
+
+pub fn verify(secret: &str, token: &str, now: i64) -> Option<EventsScope> {
+    // Instance names may contain dots, so split from the right.
+    let (claims, provided) = token.rsplit_once('.')?;
+    let (instances, expires_at) = claims.rsplit_once('.')?;
+    let provided = hex::decode(provided).ok()?;
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(claims.as_bytes());
+    mac.verify_slice(&provided).ok()?;
+
+    if expires_at.parse::<i64>().ok()? <= now {
+        return None;
+    }
+    let instances: Vec<String> = instances
+        .split(',')
+        .map(str::trim)
+        .filter(|instance| !instance.is_empty())
+        .map(str::to_string)
+        .collect();
+    if instances.is_empty() {
+        return None;
+    }
+    Some(EventsScope { instances })
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+use lapin::message::Delivery;
+use redis::aio::ConnectionManager;
+use deadpool_postgres::Pool;
+use crate::config::config::DotEnv;
+use crate::media::store::MediaStore;
+use crate::phone::normalize::PhoneNormalizer;
+use crate::process::error::ProcessError;
+
+#[derive(Clone)]
+pub struct Context {
+    pub db: Pool,
+    pub redis: ConnectionManager,
+    pub env: Arc<DotEnv>,
+    pub media: Arc<MediaStore>,
+    pub http: reqwest::Client,
+    pub phone: Arc<PhoneNormalizer>,
+    // The channel of the consumer handling the delivery, set by `run_consumer`.
+    pub channel: Option<lapin::Channel>,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    Ack,
+    Retry(String),
+    Reject(String),
+    Route(String),
+}
+
+impl From<Result<(), ProcessError>> for Outcome {
+    fn from(result: Result<(), ProcessError>) -> Self {
+        match result {
+            Ok(_) => Outcome::Ack,
+            Err(e) if e.is_transient() => Outcome::Retry(e.to_string()),
+            Err(e) => Outcome::Reject(e.to_string()),
+        }
+    }
+}
+
+#[async_trait]
+pub trait MessageHandler: Send + Sync {
+    async fn handle(&self, delivery: &Delivery, ctx: &Context) -> Outcome;
+}
@@ -0,0 +1,29 @@
+use async_trait::async_trait;
+use lapin::message::Delivery;
+use crate::handlers::handler::{Context, MessageHandler, Outcome};
+use crate::parser::provider::Provider;
+use crate::process::incoming::process_incoming;
+use crate::process::policy::Ingested;
+use crate::rabbit::delivery::ROUTED_FROM_HEADER;
+use crate::rabbit::headers::header_str;
+
+pub const PROVIDER_HEADER: &str = "x-provider";
+
+pub struct IncomingHandler {
+    pub provider: Option<Provider>,
+}
+
+#[async_trait]
+impl MessageHandler for IncomingHandler {
+    async fn handle(&self, delivery: &Delivery, ctx: &Context) -> Outcome {
+        let provider = self.provider.or_else(|| {
+            header_str(delivery, PROVIDER_HEADER).and_then(|name| Provider::from_name(&name))
+        });
+        let routed = header_str(delivery, ROUTED_FROM_HEADER).is_some();
+        match process_incoming(&delivery.data, ctx, provider, routed, delivery.redelivered).await {
+            Ok(Ingested::Handled) => Outcome::Ack,
+            Ok(Ingested::Route(queue)) => Outcome::Route(queue),
+            Err(e) => Err(e).into(),
+        }
+    }
+}
@@ -0,0 +1,6 @@
+pub mod handler;
+pub mod registry;
+pub mod outgoing;
+pub mod incoming;
+pub mod sent;
+pub mod status;
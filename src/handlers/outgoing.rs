@@ -0,0 +1,31 @@
+use async_trait::async_trait;
+use lapin::message::Delivery;
+use crate::handlers::handler::{Context, MessageHandler, Outcome};
+use crate::process::idempotency::{process_once, OPERATION_SCOPE};
+use crate::process::outgoing::process_outgoing;
+use crate::process::reply::ReplyTarget;
+use crate::rabbit::retry::attempts_made;
+use crate::rabbit::headers::header_str;
+
+pub const MESSAGE_ID_HEADER: &str = "x-message-id";
+
+pub struct OutgoingHandler;
+
+#[async_trait]
+impl MessageHandler for OutgoingHandler {
+    async fn handle(&self, delivery: &Delivery, ctx: &Context) -> Outcome {
+        let message_id = delivery.properties.message_id().as_ref()
+            .map(|id| id.to_string())
+            .or_else(|| header_str(delivery, MESSAGE_ID_HEADER))
+            .unwrap_or_default();
+        let reply = ReplyTarget {
+            correlation_id: delivery.properties.correlation_id().as_ref().map(|id| id.to_string()),
+            message_id: Some(message_id.clone()),
+            reply_to: delivery.properties.reply_to().as_ref().map(|queue| queue.to_string()),
+            attempt: attempts_made(delivery) + 1,
+        };
+        process_once(ctx, OPERATION_SCOPE, &message_id, delivery.redelivered, || {
+            process_outgoing(&delivery.data, ctx, &reply)
+        }).await.into()
+    }
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::handlers::handler::MessageHandler;
+use crate::handlers::incoming::IncomingHandler;
+use crate::handlers::outgoing::OutgoingHandler;
+use crate::handlers::sent::SentMessageHandler;
+use crate::handlers::status::StatusHandler;
+use crate::parser::provider::Provider;
+
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: HashMap<String, Arc<dyn MessageHandler>>,
+}
+
+impl HandlerRegistry {
+    pub fn with_defaults() -> Self {
+        let mut registry = HandlerRegistry::default();
+        registry.register("outgoing", Arc::new(OutgoingHandler));
+        registry.register("incoming", Arc::new(IncomingHandler { provider: None }));
+        registry.register("evolution", Arc::new(IncomingHandler { provider: Some(Provider::Evolution) }));
+        registry.register("wuzapi", Arc::new(IncomingHandler { provider: Some(Provider::Wuzapi) }));
+        registry.register("send_message", Arc::new(SentMessageHandler));
+        registry.register("status", Arc::new(StatusHandler { provider: None }));
+        registry
+    }
+
+    pub fn register(&mut self, name: &str, handler: Arc<dyn MessageHandler>) {
+        self.handlers.insert(name.to_string(), handler);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn MessageHandler>> {
+        self.handlers.get(name).cloned()
+    }
+}
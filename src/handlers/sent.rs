@@ -0,0 +1,13 @@
+use async_trait::async_trait;
+use lapin::message::Delivery;
+use crate::handlers::handler::{Context, MessageHandler, Outcome};
+use crate::process::sent::process_sent_message;
+
+pub struct SentMessageHandler;
+
+#[async_trait]
+impl MessageHandler for SentMessageHandler {
+    async fn handle(&self, delivery: &Delivery, ctx: &Context) -> Outcome {
+        process_sent_message(&delivery.data, ctx, delivery.redelivered).await.into()
+    }
+}
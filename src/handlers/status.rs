@@ -0,0 +1,21 @@
+use async_trait::async_trait;
+use lapin::message::Delivery;
+use crate::handlers::handler::{Context, MessageHandler, Outcome};
+use crate::handlers::incoming::PROVIDER_HEADER;
+use crate::parser::provider::Provider;
+use crate::process::status::process_status;
+use crate::rabbit::headers::header_str;
+
+pub struct StatusHandler {
+    pub provider: Option<Provider>,
+}
+
+#[async_trait]
+impl MessageHandler for StatusHandler {
+    async fn handle(&self, delivery: &Delivery, ctx: &Context) -> Outcome {
+        let provider = self.provider.or_else(|| {
+            header_str(delivery, PROVIDER_HEADER).and_then(|name| Provider::from_name(&name))
+        });
+        process_status(&delivery.data, ctx, provider).await.into()
+    }
+}
@@ -5,20 +5,27 @@ mod parser;
 mod database;
 mod process;
 mod redis_mod;
+mod handlers;
+mod media;
+mod cli;
+mod events;
+mod phone;
 
-use log::{error, info, warn};
+use log::{error, info};
 use tokio::time::{sleep, Duration};
-use futures::pin_mut;
-use futures::StreamExt;
 use tokio::select;
 use tokio::signal;
-use tokio_postgres::Client;
 use std::sync::Arc;
 use env_logger::{Builder, Env};
-use redis::aio::MultiplexedConnection;
-use tokio::sync::Mutex;
-use crate::parser::library::SendMessageResponse;
-use crate::redis_mod::redis::{insert_message_to_chat, normalize_chat_id};
+use tokio_util::sync::CancellationToken;
+use crate::handlers::handler::Context;
+use crate::handlers::registry::HandlerRegistry;
+use crate::media::store::MediaStore;
+use crate::phone::normalize::PhoneNormalizer;
+use crate::cli::command::{parse_args, Command, USAGE};
+use crate::cli::migrate::{run_migrate, run_migrate_redis};
+use crate::cli::merge::run_merge_chats;
+use crate::cli::token::run_events_token;
 
 #[tokio::main]
 async fn main() {
@@ -29,172 +36,184 @@ async fn main() {
         .format_module_path(true)
         .init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
     let env = match config::config::load_dotenv() {
-        Ok(env) => env,
+        Ok(env) => Arc::new(env),
         Err(e) => {
             error!("ERROR: Couldn't retrieve .env: {}", e);
             return;
         }
     };
 
+    let one_shot = match command {
+        Command::Consume => None,
+        Command::Migrate(migrate) => Some(("Migration", run_migrate(&env, migrate).await)),
+        Command::MigrateRedis => Some(("Redis migration", run_migrate_redis(&env).await)),
+        Command::MergeChats { dry_run } => Some(("Chat merge", run_merge_chats(&env, dry_run).await)),
+        Command::EventsToken { instances, ttl_secs } => Some(("Events token generation", run_events_token(&env, &instances, ttl_secs))),
+    };
+    if let Some((name, result)) = one_shot {
+        if let Err(e) = result {
+            error!("ERROR: {} failed: {}", name, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     info!("Starting application - Check Logs below...");
 
     info!("Starting WaSolConsumer");
 
-    let redis_conn = match crate::redis_mod::redis::connect_redis(&env.redis_url).await {
-        Ok(conn) => Arc::new(Mutex::new(conn)),
+    let registry = HandlerRegistry::with_defaults();
+    let mut bindings = Vec::new();
+    for queue in &env.queues {
+        match registry.get(&queue.handler) {
+            Some(handler) => bindings.push((queue, handler)),
+            None => {
+                error!("ERROR: No handler named '{}' is registered for queue {}", queue.handler, queue.name);
+                return;
+            }
+        }
+    }
+
+    let media = match MediaStore::from_config(&env.media) {
+        Ok(media) => Arc::new(media),
         Err(e) => {
-            error!("ERROR: Couldn't connect to Redis: {}", e);
+            error!("ERROR: Couldn't set up media storage: {}", e);
+            return;
+        }
+    };
+    let phone = match PhoneNormalizer::from_config(&env.phone) {
+        Ok(phone) => Arc::new(phone),
+        Err(e) => {
+            error!("ERROR: Couldn't set up phone normalization: {}", e);
+            return;
+        }
+    };
+    let http = match api::requests::build_client(&env.http) {
+        Ok(http) => http,
+        Err(e) => {
+            error!("ERROR: Couldn't set up HTTP client: {}", e);
             return;
         }
     };
 
+    let db_pool = match database::connect::create_pool(&env.db_url, &env.db) {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("ERROR: Couldn't set up Database pool: {}", e);
+            return;
+        }
+    };
+
+    let shutdown = CancellationToken::new();
+    tokio::spawn(wait_for_shutdown_signal(shutdown.clone()));
+
+    if env.events.gateway_addr.is_some() {
+        let env = Arc::clone(&env);
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = events::gateway::run_gateway(&env.events, &env.redis_url, shutdown).await {
+                error!("ERROR: Events gateway failed: {}", e);
+            }
+        });
+    }
+
+    let redis_conn = select! {
+        conn = crate::redis_mod::redis::connect_redis_with_retry(&env.redis_url) => conn,
+        _ = shutdown.cancelled() => return,
+    };
+
     loop {
-        let db_client = match database::connect::connect_db(&env.db_url).await {
+        let mut db_client = match db_pool.get().await {
             Ok(db_client) => db_client,
             Err(e) => {
                 error!("ERROR: Couldn't connect to Database, retrying... : {}",e);
-                sleep(Duration::from_secs(30)).await;
-                continue;
+                select! {
+                    _ = sleep(Duration::from_secs(30)) => continue,
+                    _ = shutdown.cancelled() => break,
+                }
             }
         };
-        let db_client = Arc::new(db_client);
-        info!("Setting up Outgoing and Incoming Request consumers...");
+        if env.auto_migrate {
+            match database::migrate::migrate_up(&mut db_client).await {
+                Ok(applied) => info!("Applied {} pending migration(s)", applied),
+                Err(e) => {
+                    error!("ERROR: Couldn't apply database migrations: {}", e);
+                    break;
+                }
+            }
+        }
+        drop(db_client);
+        let ctx = Context {
+            db: db_pool.clone(),
+            redis: redis_conn.clone(),
+            env: Arc::clone(&env),
+            media: Arc::clone(&media),
+            http: http.clone(),
+            phone: Arc::clone(&phone),
+            channel: None,
+        };
+        info!("Setting up consumers for {} queues...", bindings.len());
 
-        let outgoing = run_consumer(&env.rabbit_url, &db_client, "outgoing_requests", None);
-        let incoming = run_consumer(&env.rabbit_url, &db_client, "incoming_requests", Some(Arc::clone(&redis_conn)));
-        let upsert = run_consumer(&env.rabbit_url, &db_client, "evolution.messages.upsert", Some(Arc::clone(&redis_conn)));
-        let send = run_consumer(&env.rabbit_url, &db_client, "evolution.send.message", Some(Arc::clone(&redis_conn)));
+        let consumers = bindings.iter().map(|(queue, handler)| {
+            rabbit::consumer::run_consumer(&env.rabbit_url, queue, Arc::clone(handler), ctx.clone(), env.shutdown_timeout, &shutdown)
+        });
 
-        let result = tokio::try_join!(outgoing, incoming, upsert, send);
+        let result = futures::future::try_join_all(consumers).await;
         match result {
             Ok(_) => {
                 info!("Application shutdown requested");
+                drop(ctx);
                 break;
             }
             Err(e) => {
                 error!("Error in consumer loop: {}", e);
                 println!("ERROR: Consumer loop failed: {}", e);
                 info!("Reconnecting in 5 seconds...");
-                sleep(Duration::from_secs(5)).await;
+                select! {
+                    _ = sleep(Duration::from_secs(5)) => {},
+                    _ = shutdown.cancelled() => break,
+                }
             }
         }
     }
-}
-
-async fn run_consumer(
-    rabbit_url: &str,
-    db_client: &Arc<Client>,
-    queue_name: &str,
-    redis_conn: Option<Arc<Mutex<MultiplexedConnection>>>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let (mut consumer, _) = match rabbit::setup_rabbit::create_rabbitmq_consumer(rabbit_url, queue_name).await {
-        Some((consumer, connection)) => (consumer, connection),
-        None => return Err("Failed to create RabbitMQ consumer".into()),
-    };
 
-    info!("Consumer ready, waiting for webhooks...");
-    info!("Press Ctrl+C to exit");
-
-    loop {
-        let ctrl_c_future = signal::ctrl_c();
-        pin_mut!(ctrl_c_future);
-
-        select! {
-            delivery_result = consumer.next() => {
-                match delivery_result {
-                    Some(Ok(delivery)) => {
-                        let data = delivery.data.clone();
-                        let db = Arc::clone(db_client);
-                        let queue_name = queue_name.to_string();
-                        if queue_name == "incoming_requests" {
-                            let redis_conn = redis_conn.as_ref().unwrap().clone();
-                            tokio::spawn(async move {
-                                let mut redis_conn = redis_conn.lock().await;
-                                match process::incoming::process_incoming(&data, &mut *redis_conn).await {
-                                    Ok(_) => {
-                                        info!("Successfully processed incoming request");
-                                    }
-                                    Err(e) => {
-                                        error!("Error processing incoming request: {}", e);
-                                    }
-                                }
-                            });
-                        } else if queue_name == "outgoing_requests" {
-                            tokio::spawn(async move {
-                                match process::outgoing::process_outgoing(&data, &db).await {
-                                    Ok(_) => {
-                                        info!("Successfully processed outgoing request");
-                                    }
-                                    Err(e) => {
-                                        error!("Error in consumer loop: {}", e);
-                                        println!("ERROR: Consumer loop failed: {}", e);
-                                        info!("Reconnecting in 5 seconds...");
-                                        sleep(Duration::from_secs(5)).await;
-                                    }
-                                }
-                            });
-                        } else if queue_name == "evolution.messages.upsert" {
-                            let redis_conn = redis_conn.as_ref().unwrap().clone();
-                            tokio::spawn(async move {
-                                let mut redis_conn = redis_conn.lock().await;
-                                match process::incoming::process_incoming(&data, &mut *redis_conn).await {
-                                    Ok(_) => {
-                                        info!("Successfully processed outgoing request");
-                                    }
-                                    Err(e) => {
-                                        error!("Error in consumer loop: {}", e);
-                                        println!("ERROR: Consumer loop failed: {}", e);
-                                        info!("Reconnecting in 5 seconds...");
-                                        sleep(Duration::from_secs(5)).await;
-                                    }
-                                }
-                            });
-                        } else if queue_name == "evolution.send.message" {
-                            let redis_conn = redis_conn.as_ref().unwrap().clone();
-                            tokio::spawn(async move {
-                                let mut redis_conn = redis_conn.lock().await;
-                                match serde_json::from_slice::<SendMessageResponse>(&data) {
-                                    Ok(response) => {
-                                        if let Some(status_string) = &response.status_string {
-                                            if let (Some(key), Some(message)) = (&status_string.key, &status_string.message) {
-                                                let chat_id = normalize_chat_id(&key.remote_jid);
-                                                let remote_jid = &chat_id;
-                                                let message_json = serde_json::to_string(&message).unwrap_or_default();
-                                                if let Err(e) = insert_message_to_chat(&mut *redis_conn, &chat_id, &message_json, remote_jid, None, None).await {
-                                                    error!("Failed to insert message to Redis: {}", e);
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to deserialize SendMessageResponse: {}", e);
-                                    }
-                                }
-                            });
-                        }
-
-                        if let Err(e) = delivery.ack(lapin::options::BasicAckOptions::default()).await {
-                            error!("Failed to acknowledge message: {}", e);
-                        }
-                    },
-                    Some(Err(e)) => {
-                        error!("Error receiving message: {}", e);
-                        return Err(Box::new(e));
-                    },
-                    None => {
-                        warn!("Consumer channel closed");
-                        return Err("Consumer channel closed unexpectedly".into());
-                    }
-                }
-            },
+    db_pool.close();
+    info!("Database pool closed");
+    drop(redis_conn);
+    info!("Redis connection closed, bye!");
+}
 
-            _ = ctrl_c_future => {
-                info!("Received shutdown signal");
-                break;
+async fn wait_for_shutdown_signal(shutdown: CancellationToken) {
+    #[cfg(unix)]
+    {
+        let mut sigterm = match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(sigterm) => sigterm,
+            Err(e) => {
+                error!("Failed to install SIGTERM handler: {}", e);
+                let _ = signal::ctrl_c().await;
+                shutdown.cancel();
+                return;
             }
+        };
+        select! {
+            _ = signal::ctrl_c() => info!("Received Ctrl+C, shutting down..."),
+            _ = sigterm.recv() => info!("Received SIGTERM, shutting down..."),
         }
     }
-
-    Ok(())
-}
\ No newline at end of file
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+        info!("Received Ctrl+C, shutting down...");
+    }
+    shutdown.cancel();
+}
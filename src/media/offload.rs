@@ -0,0 +1,97 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sha2::{Digest, Sha256};
+use log::{info, warn};
+use crate::api::evolution::get_base64_from_media_message;
+use crate::handlers::handler::Context;
+use crate::media::store::MediaStore;
+use crate::parser::library::{IncomingMessage, MediaReference};
+use crate::process::error::ProcessError;
+
+const MEDIA_TYPES: [&str; 5] = ["image", "video", "audio", "document", "sticker"];
+
+fn extension_for(mime_type: &str) -> &str {
+    match mime_type {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        "video/mp4" => "mp4",
+        "video/3gpp" => "3gp",
+        "audio/ogg" => "ogg",
+        "audio/mpeg" => "mp3",
+        "audio/mp4" => "m4a",
+        "application/pdf" => "pdf",
+        _ => "bin",
+    }
+}
+
+// Instance names come from webhooks, so keep them to a single safe path segment.
+fn key_segment(instance: &str) -> String {
+    let segment: String = instance
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if segment.is_empty() { "unknown".to_string() } else { segment }
+}
+
+fn strip_data_url(encoded: &str) -> &str {
+    match encoded.split_once(";base64,") {
+        Some((prefix, data)) if prefix.starts_with("data:") => data,
+        _ => encoded,
+    }
+}
+
+pub async fn offload_media(incoming: &mut IncomingMessage, ctx: &Context) -> Result<(), ProcessError> {
+    if !MEDIA_TYPES.contains(&incoming.message.msg_type.as_str()) {
+        return Ok(());
+    }
+
+    let encoded = match incoming.media_base64.take().filter(|b| !b.is_empty()) {
+        Some(encoded) => encoded,
+        None => match &incoming.media_fetch {
+            Some(fetch) if ctx.env.media.fetch_from_evolution => get_base64_from_media_message(&ctx.http, fetch).await?,
+            _ => {
+                warn!("No media content available for message {}", incoming.message.id);
+                return Ok(());
+            }
+        },
+    };
+
+    let cleaned: String = strip_data_url(&encoded).chars().filter(|c| !c.is_whitespace()).collect();
+    let bytes = STANDARD.decode(&cleaned)
+        .map_err(|e| ProcessError::Permanent(format!("invalid media base64: {}", e)))?;
+
+    let declared = incoming.message.mime_type.as_deref()
+        .map(|m| m.split(';').next().unwrap_or(m).trim().to_string());
+    let detected = infer::get(&bytes);
+    let mime_type = detected.map(|kind| kind.mime_type().to_string())
+        .or(declared)
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let extension = detected.map(|kind| kind.extension()).unwrap_or_else(|| extension_for(&mime_type));
+
+    let message = &mut incoming.message;
+    message.mime_type = Some(mime_type.clone());
+
+    if let MediaStore::Inline = ctx.media.as_ref() {
+        message.body = format!("data:{};base64,{}", mime_type, cleaned);
+        return Ok(());
+    }
+
+    let sha256 = hex::encode(Sha256::digest(&bytes));
+    let key = format!("{}/{}/{}.{}", key_segment(&incoming.instance_id), message.msg_type, sha256, extension);
+    let size = bytes.len();
+
+    if let Some(url) = ctx.media.put(&key, bytes).await? {
+        info!("Stored {} bytes of media for message {} at {}", size, message.id, url);
+        message.body = url.clone();
+        message.media = Some(MediaReference {
+            key,
+            url,
+            size,
+            mime_type,
+            sha256,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+use object_store::{ObjectStore, PutPayload};
+use log::info;
+use crate::config::config::MediaConfig;
+use crate::process::error::ProcessError;
+
+pub enum MediaStore {
+    Inline,
+    Object {
+        store: Arc<dyn ObjectStore>,
+        base_url: String,
+    },
+}
+
+impl MediaStore {
+    pub fn from_config(config: &MediaConfig) -> Result<Self, String> {
+        match config.storage.as_str() {
+            "inline" => Ok(MediaStore::Inline),
+            "filesystem" => {
+                std::fs::create_dir_all(&config.dir)
+                    .map_err(|e| format!("Couldn't create media dir {}: {}", config.dir, e))?;
+                let store = LocalFileSystem::new_with_prefix(&config.dir)
+                    .map_err(|e| format!("Couldn't open media dir {}: {}", config.dir, e))?;
+                let base_url = match &config.public_url {
+                    Some(url) => url.clone(),
+                    None => {
+                        let dir = std::fs::canonicalize(&config.dir)
+                            .map_err(|e| format!("Couldn't resolve media dir {}: {}", config.dir, e))?;
+                        format!("file://{}", dir.display())
+                    }
+                };
+                info!("Storing media on the filesystem at {}", config.dir);
+                Ok(MediaStore::Object { store: Arc::new(store), base_url })
+            }
+            "s3" => {
+                let bucket = config.s3_bucket.as_deref()
+                    .ok_or_else(|| "MEDIA_S3_BUCKET is required when MEDIA_STORAGE=s3".to_string())?;
+                let mut builder = AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .with_region(&config.s3_region);
+                if let Some(endpoint) = &config.s3_endpoint {
+                    builder = builder
+                        .with_endpoint(endpoint)
+                        .with_allow_http(endpoint.starts_with("http://"));
+                }
+                if let Some(access_key) = &config.s3_access_key {
+                    builder = builder.with_access_key_id(access_key);
+                }
+                if let Some(secret_key) = &config.s3_secret_key {
+                    builder = builder.with_secret_access_key(secret_key);
+                }
+                let store = builder.build()
+                    .map_err(|e| format!("Couldn't configure S3 media store: {}", e))?;
+                let base_url = config.public_url.clone().unwrap_or_else(|| format!("s3://{}", bucket));
+                info!("Storing media on S3 bucket {}", bucket);
+                Ok(MediaStore::Object { store: Arc::new(store), base_url })
+            }
+            other => Err(format!("Unknown MEDIA_STORAGE '{}', expected inline, filesystem or s3", other)),
+        }
+    }
+
+    pub async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<Option<String>, ProcessError> {
+        match self {
+            MediaStore::Inline => Ok(None),
+            MediaStore::Object { store, base_url } => {
+                store.put(&Path::from(key), PutPayload::from(bytes)).await
+                    .map_err(|e| ProcessError::Transient(format!("media store error: {}", e)))?;
+                Ok(Some(format!("{}/{}", base_url.trim_end_matches('/'), key)))
+            }
+        }
+    }
+}
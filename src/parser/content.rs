@@ -0,0 +1,270 @@
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde_json::Value;
+use crate::parser::library::{ContextInfo, MediaMessage, NormalizedMessage, QuotedMessage, WhatsAppMessage};
+
+#[derive(Debug, Default)]
+pub struct NormalizedContent {
+    pub msg_type: String,
+    pub text: String,
+    pub body: String,
+    pub caption: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_name: Option<String>,
+    pub quoted: Option<QuotedMessage>,
+}
+
+impl NormalizedContent {
+    pub fn into_message(self, id: String, from: String, to: String, timestamp: String) -> NormalizedMessage {
+        NormalizedMessage {
+            id,
+            from,
+            to,
+            text: self.text,
+            body: self.body,
+            msg_type: self.msg_type,
+            timestamp,
+            caption: self.caption,
+            mime_type: self.mime_type,
+            file_name: self.file_name,
+            quoted: self.quoted,
+            media: None,
+        }
+    }
+}
+
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    let epoch: i64 = timestamp.parse().ok()?;
+    if epoch > 100_000_000_000 {
+        DateTime::from_timestamp_millis(epoch)
+    } else {
+        DateTime::from_timestamp(epoch, 0)
+    }
+}
+
+pub fn parse_message(value: Option<&Value>) -> WhatsAppMessage {
+    let Some(value) = value else {
+        return WhatsAppMessage::default();
+    };
+    match serde_json::from_value(value.clone()) {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Couldn't parse WhatsApp message content, dropping the fields that don't fit: {}", e);
+            let pruned = prune(value, &mut Vec::new());
+            serde_json::from_value(pruned).unwrap_or_default()
+        }
+    }
+}
+
+// Keeps every field of `value` that parses on its own, descending into objects so one bad leaf doesn't
+// take the whole message type with it. Each field is checked by parsing a message holding only that field.
+fn prune(value: &Value, path: &mut Vec<String>) -> Value {
+    let Value::Object(fields) = value else {
+        return Value::Null;
+    };
+    let mut kept = serde_json::Map::new();
+    for (key, field) in fields {
+        path.push(key.clone());
+        if fits(path, field) {
+            kept.insert(key.clone(), field.clone());
+        } else if field.is_object() {
+            let pruned = prune(field, path);
+            if fits(path, &pruned) {
+                kept.insert(key.clone(), pruned);
+            }
+        } else {
+            warn!("Dropping message field {} that doesn't match its expected type", path.join("."));
+        }
+        path.pop();
+    }
+    Value::Object(kept)
+}
+
+fn fits(path: &[String], field: &Value) -> bool {
+    let probe = path.iter().rev().fold(field.clone(), |inner, key| {
+        let mut object = serde_json::Map::new();
+        object.insert(key.clone(), inner);
+        Value::Object(object)
+    });
+    serde_json::from_value::<WhatsAppMessage>(probe).is_ok()
+}
+
+fn unwrap(message: &WhatsAppMessage) -> &WhatsAppMessage {
+    let inner = message.ephemeral_message.as_ref()
+        .or(message.view_once_message.as_ref())
+        .or(message.document_with_caption_message.as_ref())
+        .or(message.edited_message.as_ref())
+        .and_then(|wrapped| wrapped.message.as_ref());
+    match inner {
+        Some(inner) => unwrap(inner),
+        None => message,
+    }
+}
+
+fn quoted(context: Option<&ContextInfo>) -> Option<QuotedMessage> {
+    let context = context?;
+    let id = context.stanza_id.clone()?;
+    let text = context.quoted_message
+        .as_deref()
+        .map(|message| normalize(message, None, "").text)
+        .unwrap_or_default();
+    Some(QuotedMessage {
+        id,
+        participant: context.participant.clone(),
+        text,
+    })
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.as_ref().filter(|v| !v.trim().is_empty()).cloned()
+}
+
+fn media(msg_type: &str, media: &MediaMessage, base64: Option<&str>, default_mime: &str, default_text: &str) -> NormalizedContent {
+    let mime_type = non_empty(&media.mimetype);
+    let data_mime = mime_type.as_deref().unwrap_or(default_mime);
+    let data_mime = data_mime.split(';').next().unwrap_or(default_mime).trim();
+    let caption = non_empty(&media.caption);
+    let file_name = non_empty(&media.file_name).or_else(|| non_empty(&media.title));
+
+    let text = match (&caption, msg_type) {
+        (Some(caption), _) => caption.clone(),
+        (None, "document") => file_name
+            .as_ref()
+            .map(|name| format!("📄 {}", name))
+            .unwrap_or_else(|| default_text.to_string()),
+        _ => default_text.to_string(),
+    };
+
+    NormalizedContent {
+        msg_type: msg_type.to_string(),
+        text,
+        body: format!("data:{};base64,{}", data_mime, base64.unwrap_or("")),
+        caption,
+        mime_type,
+        file_name,
+        quoted: quoted(media.context_info.as_ref()),
+    }
+}
+
+fn text_only(msg_type: &str, text: String, quoted: Option<QuotedMessage>) -> NormalizedContent {
+    NormalizedContent {
+        msg_type: msg_type.to_string(),
+        body: text.clone(),
+        text,
+        quoted,
+        ..NormalizedContent::default()
+    }
+}
+
+pub fn normalize(message: &WhatsAppMessage, base64: Option<&str>, fallback_type: &str) -> NormalizedContent {
+    let message = unwrap(message);
+    let base64 = base64.or(message.base64.as_deref());
+
+    if let Some(text) = &message.conversation {
+        return text_only("text", text.clone(), None);
+    }
+    if let Some(extended) = &message.extended_text_message {
+        return text_only("text", extended.text.clone().unwrap_or_default(), quoted(extended.context_info.as_ref()));
+    }
+    if let Some(image) = &message.image_message {
+        return media("image", image, base64, "image/jpeg", "📷 Imagem enviada");
+    }
+    if let Some(video) = message.video_message.as_ref().or(message.video_note_message.as_ref()) {
+        return media("video", video, base64, "video/mp4", "🎥 Vídeo enviado");
+    }
+    if let Some(audio) = &message.audio_message {
+        return media("audio", audio, base64, "audio/ogg", "Áudio enviado");
+    }
+    if let Some(document) = &message.document_message {
+        return media("document", document, base64, "application/octet-stream", "📄 Documento enviado");
+    }
+    if let Some(sticker) = &message.sticker_message {
+        return media("sticker", sticker, base64, "image/webp", "Figurinha enviada");
+    }
+    if let Some(location) = message.location_message.as_ref().or(message.live_location_message.as_ref()) {
+        let latitude = location.degrees_latitude.unwrap_or_default();
+        let longitude = location.degrees_longitude.unwrap_or_default();
+        let label = non_empty(&location.name)
+            .or_else(|| non_empty(&location.address))
+            .unwrap_or_else(|| format!("{}, {}", latitude, longitude));
+        let msg_type = if message.live_location_message.is_some() { "live_location" } else { "location" };
+        return NormalizedContent {
+            msg_type: msg_type.to_string(),
+            text: format!("📍 {}", label),
+            body: format!("https://maps.google.com/?q={},{}", latitude, longitude),
+            quoted: quoted(location.context_info.as_ref()),
+            ..NormalizedContent::default()
+        };
+    }
+    if let Some(contact) = &message.contact_message {
+        let name = contact.display_name.clone().unwrap_or_default();
+        return NormalizedContent {
+            msg_type: "contact".to_string(),
+            text: format!("👤 {}", name),
+            body: contact.vcard.clone().unwrap_or_default(),
+            quoted: quoted(contact.context_info.as_ref()),
+            ..NormalizedContent::default()
+        };
+    }
+    if let Some(contacts) = &message.contacts_array_message {
+        let vcards: Vec<String> = contacts.contacts.iter().filter_map(|c| c.vcard.clone()).collect();
+        let label = non_empty(&contacts.display_name)
+            .unwrap_or_else(|| format!("{} contatos", contacts.contacts.len()));
+        return NormalizedContent {
+            msg_type: "contacts".to_string(),
+            text: format!("👥 {}", label),
+            body: vcards.join("\n"),
+            ..NormalizedContent::default()
+        };
+    }
+    if let Some(reaction) = &message.reaction_message {
+        let emoji = reaction.text.clone().unwrap_or_default();
+        let text = if emoji.is_empty() { "Reação removida".to_string() } else { format!("Reagiu com {}", emoji) };
+        return NormalizedContent {
+            msg_type: "reaction".to_string(),
+            text,
+            body: emoji,
+            quoted: reaction.key.as_ref().and_then(|key| key.id.clone()).map(|id| QuotedMessage {
+                id,
+                participant: None,
+                text: String::new(),
+            }),
+            ..NormalizedContent::default()
+        };
+    }
+    if let Some(poll) = &message.poll_creation_message {
+        let options: Vec<String> = poll.options.iter().filter_map(|o| o.option_name.clone()).collect();
+        return NormalizedContent {
+            msg_type: "poll".to_string(),
+            text: format!("📊 {}", poll.name.clone().unwrap_or_default()),
+            body: options.join("\n"),
+            ..NormalizedContent::default()
+        };
+    }
+    if let Some(reply) = &message.buttons_response_message {
+        let mut content = text_only("button_reply", reply.selected_display_text.clone().unwrap_or_default(), quoted(reply.context_info.as_ref()));
+        content.body = reply.selected_button_id.clone().unwrap_or_default();
+        return content;
+    }
+    if let Some(reply) = &message.template_button_reply_message {
+        let mut content = text_only("button_reply", reply.selected_display_text.clone().unwrap_or_default(), quoted(reply.context_info.as_ref()));
+        content.body = reply.selected_id.clone().unwrap_or_default();
+        return content;
+    }
+    if let Some(reply) = &message.list_response_message {
+        let mut content = text_only("list_reply", reply.title.clone().unwrap_or_default(), quoted(reply.context_info.as_ref()));
+        content.body = reply.single_select_reply
+            .as_ref()
+            .and_then(|r| r.selected_row_id.clone())
+            .unwrap_or_default();
+        return content;
+    }
+
+    NormalizedContent {
+        msg_type: if fallback_type.is_empty() { "unknown".to_string() } else { fallback_type.to_string() },
+        ..NormalizedContent::default()
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::Utc;
+use serde_json::Value;
+use crate::parser::content::{normalize, parse_message, parse_timestamp};
+use crate::parser::library::{IncomingMessage, MediaFetch};
+use crate::parser::status::{MessageStatus, StatusUpdate};
+use crate::process::error::ProcessError;
+
+fn str_at<'a>(value: &'a Value, pointer: &str) -> &'a str {
+    value.pointer(pointer).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+// Webhooks carry the message under `data`; send responses forwarded to the incoming queues carry it
+// under `status_string`, whose key may come in snake_case.
+fn message_body(value: &Value) -> Option<&Value> {
+    ["/data", "/status_string"]
+        .into_iter()
+        .filter_map(|pointer| value.pointer(pointer))
+        .find(|body| body.pointer("/key/remoteJid").or_else(|| body.pointer("/key/remote_jid")).is_some())
+}
+
+fn first_str<'a>(value: &'a Value, pointers: &[&str]) -> &'a str {
+    pointers.iter().map(|pointer| str_at(value, pointer)).find(|s| !s.is_empty()).unwrap_or("")
+}
+
+pub fn matches(value: &Value) -> bool {
+    message_body(value).is_some()
+}
+
+pub fn parse(value: &Value) -> Result<Option<IncomingMessage>, ProcessError> {
+    let data = message_body(value)
+        .ok_or_else(|| ProcessError::Permanent("Evolution webhook without a remoteJid".to_string()))?;
+    let remote_jid = first_str(data, &["/key/remoteJid", "/key/remote_jid"]);
+    let message_id = str_at(data, "/key/id");
+    if message_id.is_empty() {
+        return Err(ProcessError::Permanent(format!("Evolution webhook for {} without a message id", remote_jid)));
+    }
+
+    let message = parse_message(data.get("message"));
+    let media_fetch = match (str_at(value, "/server_url"), str_at(value, "/instance"), str_at(value, "/apikey")) {
+        ("", _, _) | (_, "", _) | (_, _, "") => None,
+        (server_url, instance, apikey) => Some(MediaFetch {
+            server_url: server_url.to_string(),
+            instance: instance.to_string(),
+            apikey: apikey.to_string(),
+            message_id: message_id.to_string(),
+        }),
+    };
+    let content = normalize(&message, None, first_str(data, &["/messageType", "/message_type"]));
+
+    Ok(Some(IncomingMessage {
+        remote_jid: remote_jid.to_string(),
+        instance_id: str_at(value, "/instance").to_string(),
+        message_id: message_id.to_string(),
+        from_me: data.pointer("/key/fromMe")
+            .or_else(|| data.pointer("/key/from_me"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+        message: content.into_message(
+            format!("msg_{}", message_id),
+            str_at(value, "/sender").to_string(),
+            remote_jid.to_string(),
+            str_at(value, "/date_time").to_string(),
+        ),
+        media_base64: data.pointer("/message/base64").and_then(|v| v.as_str()).map(str::to_string),
+        media_fetch,
+    }))
+}
+
+pub fn matches_status(value: &Value) -> bool {
+    let event = str_at(value, "/event").to_ascii_lowercase().replace('_', ".");
+    event == "messages.update"
+        || value.pointer("/data/keyId").is_some()
+        || value.pointer("/data/update").is_some()
+        || value.pointer("/data/0/update").is_some()
+}
+
+pub fn parse_status(value: &Value) -> Result<Vec<StatusUpdate>, ProcessError> {
+    let fallback = parse_timestamp(str_at(value, "/date_time")).unwrap_or_else(Utc::now);
+    let entries: Vec<&Value> = match value.get("data") {
+        Some(Value::Array(items)) => items.iter().collect(),
+        Some(data) => vec![data],
+        None => return Err(ProcessError::Permanent("Evolution status event without data".to_string())),
+    };
+
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| {
+            let message_id = entry.get("keyId").or_else(|| entry.pointer("/key/id"))?.as_str()?;
+            let status = entry.get("status").or_else(|| entry.pointer("/update/status"))?;
+            Some(StatusUpdate {
+                instance_id: str_at(value, "/instance").to_string(),
+                message_id: message_id.to_string(),
+                remote_jid: entry.get("remoteJid")
+                    .or_else(|| entry.pointer("/key/remoteJid"))
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("")
+                    .to_string(),
+                status: MessageStatus::from_ack(status)?,
+                timestamp: entry.get("datetime")
+                    .and_then(|v| v.as_i64().map(|t| t.to_string()).or_else(|| v.as_str().map(str::to_string)))
+                    .and_then(|t| parse_timestamp(&t))
+                    .unwrap_or(fallback),
+            })
+        })
+        .collect())
+}
@@ -3,144 +3,448 @@ use serde::Deserialize;
 use serde::Serialize;
 
 
+pub const ENVELOPE_VERSION: u32 = 1;
+
+#[derive(Deserialize)]
+pub struct Envelope {
+    pub version: u32,
+    #[serde(flatten)]
+    pub operation: Operation,
+}
+
 #[derive(Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase")]
+pub enum Operation {
+    UpsertChat(Chat),
+    UpsertCustomer(Customer),
+    #[serde(alias = "sendMessage")]
+    UpsertMessage(Message),
+    SendRequest(Request),
+    SendText(SendText),
+    SendMedia(SendMedia),
+    SendAudio(SendAudio),
+    SendLocation(SendLocation),
+    SendContact(SendContact),
+    SendReaction(SendReaction),
+    MarkMessageAsRead(MarkMessageAsRead),
+    DeleteMessage(DeleteMessage),
+    CheckWhatsAppNumbers(CheckWhatsAppNumbers),
+    SendPresence(SendPresence),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Request {
     pub action: String,
     pub method: String,
     pub url: String,
     pub headers: HashMap<String, String>,
-    pub body: Option<HashMap<String, String>>,
-    pub params: Option<HashMap<String, String>>
+    pub body: Option<serde_json::Value>,
+    pub params: Option<serde_json::Value>,
+    #[serde(alias = "correlation_id")]
+    pub correlation_id: Option<String>,
+    // Instance whose rate limit the request spends, when it sends through a WhatsApp provider.
+    pub instance: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboundTarget {
+    pub instance: String,
+    pub number: String,
+    #[serde(alias = "correlation_id")]
+    pub correlation_id: Option<String>,
+    pub delay: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendText {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    pub text: String,
+    #[serde(alias = "quoted_id")]
+    pub quoted_id: Option<String>,
+    // Author of the quoted message; Wuzapi needs it to render the quote.
+    #[serde(alias = "quoted_participant")]
+    pub quoted_participant: Option<String>,
+    #[serde(alias = "link_preview")]
+    pub link_preview: Option<bool>,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMedia {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    #[serde(alias = "mediatype")]
+    #[serde(alias = "media_type")]
+    pub media_type: String,
+    pub media: String,
+    #[serde(alias = "mime_type")]
+    pub mime_type: Option<String>,
+    pub caption: Option<String>,
+    #[serde(alias = "file_name")]
+    pub file_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendAudio {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    pub audio: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendLocation {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub name: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactCard {
+    #[serde(alias = "full_name")]
+    pub full_name: String,
+    #[serde(alias = "phone_number")]
+    pub phone_number: String,
+    pub organization: Option<String>,
+    pub email: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendContact {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    pub contacts: Vec<ContactCard>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendReaction {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    #[serde(alias = "message_id")]
+    pub message_id: String,
+    #[serde(default)]
+    #[serde(alias = "from_me")]
+    pub from_me: bool,
+    pub reaction: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MarkMessageAsRead {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    #[serde(alias = "message_ids")]
+    pub message_ids: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteMessage {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    #[serde(alias = "message_id")]
+    pub message_id: String,
+    #[serde(default = "default_true")]
+    #[serde(alias = "from_me")]
+    pub from_me: bool,
+    pub participant: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SendPresence {
+    #[serde(flatten)]
+    pub target: OutboundTarget,
+    pub presence: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckWhatsAppNumbers {
+    pub instance: String,
+    pub numbers: Vec<String>,
+    #[serde(alias = "correlation_id")]
+    pub correlation_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Chat {
     pub id: i32,
     pub situation: String,
+    #[serde(alias = "is_active")]
     pub is_active: bool,
+    #[serde(alias = "agent_id")]
     pub agent_id: Option<i32>,
     pub tabulation: Option<String>,
+    #[serde(alias = "customer_id")]
     pub customer_id: i32,
 }
 
 #[derive(Deserialize)]
-
+#[serde(rename_all = "camelCase")]
 pub struct Message {
     pub id: i32,
     pub from: String,
     pub to: String,
     pub delivered: bool,
     pub text: String,
+    #[serde(alias = "chat_id")]
     pub chat_id: i32
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Customer {
     pub id: i32,
     pub name: String,
     pub number: String,
+    #[serde(alias = "last_chat_id")]
     pub last_chat_id: Option<String>
 }
 
-#[derive(Deserialize)]
-pub struct RabbitResponse {
-    pub webhook: WebhookMessage,
-    pub chat_id: String,
+// Only the fields the sent handler reads; serde skips the rest of Evolution's response.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageKey {
+    pub remote_jid: String,
+    pub id: String,
 }
 
-#[derive(Deserialize)]
-pub struct WebhookMessage {
-    pub headers: WebhookHeaders,
-    pub params: HashMap<String, String>,
-    pub query: HashMap<String, String>,
-    pub body: WebhookBody,
-    pub webhookUrl: String,
-    pub executionMode: String,
+#[derive(Deserialize, Debug)]
+pub struct SendMessageResponse {
+    // Instance name, the same identifier the webhooks use; the `instanceId` inside `status_string` is an internal UUID.
+    #[serde(alias = "instanceName")]
+    pub instance: Option<String>,
+    pub status_string: Option<StatusString>,
 }
 
-#[derive(Deserialize)]
-pub struct WebhookHeaders {
-    pub host: String,
-    #[serde(rename = "user-agent")]
-    pub user_agent: String,
-    #[serde(rename = "content-length")]
-    pub content_length: String,
-    #[serde(rename = "accept-encoding")]
-    pub accept_encoding: String,
-    #[serde(rename = "content-type")]
-    pub content_type: String,
-    #[serde(rename = "x-forwarded-for")]
-    pub x_forwarded_for: String,
-    #[serde(rename = "x-forwarded-host")]
-    pub x_forwarded_host: String,
-    #[serde(rename = "x-forwarded-port")]
-    pub x_forwarded_port: String,
-    #[serde(rename = "x-forwarded-proto")]
-    pub x_forwarded_proto: String,
-    #[serde(rename = "x-forwarded-server")]
-    pub x_forwarded_server: String,
-    #[serde(rename = "x-real-ip")]
-    pub x_real_ip: String,
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusString {
+    pub key: Option<MessageKey>,
+    pub message: Option<serde_json::Value>,
+    pub message_timestamp: Option<i64>,
+    pub message_type: Option<String>,
 }
 
-#[derive(Deserialize)]
-pub struct WebhookBody {
-    pub event: String,
-    pub instance: String,
-    pub data: WebhookData,
-    pub destination: String,
-    #[serde(rename = "date_time")]
-    pub date_time: String,
-    pub sender: String,
-    #[serde(rename = "server_url")]
-    pub server_url: String,
-    pub apikey: String,
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct WhatsAppMessage {
+    pub conversation: Option<String>,
+    pub extended_text_message: Option<ExtendedTextMessage>,
+    pub image_message: Option<MediaMessage>,
+    pub video_message: Option<MediaMessage>,
+    #[serde(alias = "ptvMessage")]
+    pub video_note_message: Option<MediaMessage>,
+    pub audio_message: Option<MediaMessage>,
+    pub document_message: Option<MediaMessage>,
+    pub document_with_caption_message: Option<Box<WrappedMessage>>,
+    pub sticker_message: Option<MediaMessage>,
+    pub location_message: Option<LocationMessage>,
+    pub live_location_message: Option<LocationMessage>,
+    pub contact_message: Option<ContactMessage>,
+    pub contacts_array_message: Option<ContactsArrayMessage>,
+    pub reaction_message: Option<ReactionMessage>,
+    #[serde(alias = "pollCreationMessageV2", alias = "pollCreationMessageV3")]
+    pub poll_creation_message: Option<PollCreationMessage>,
+    pub buttons_response_message: Option<ButtonsResponseMessage>,
+    pub list_response_message: Option<ListResponseMessage>,
+    pub template_button_reply_message: Option<TemplateButtonReplyMessage>,
+    pub ephemeral_message: Option<Box<WrappedMessage>>,
+    #[serde(alias = "viewOnceMessageV2", alias = "viewOnceMessageV2Extension")]
+    pub view_once_message: Option<Box<WrappedMessage>>,
+    pub edited_message: Option<Box<WrappedMessage>>,
+    pub base64: Option<String>,
 }
 
-#[derive(Deserialize)]
-pub struct WebhookData {
-    pub key: MessageKey,
-    #[serde(rename = "pushName")]
-    pub push_name: String,
-    pub message: MessageContent,
-    #[serde(rename = "messageType")]
-    pub message_type: String,
-    #[serde(rename = "messageTimestamp")]
-    pub message_timestamp: i64,
-    #[serde(rename = "instanceId")]
-    pub instance_id: String,
-    pub source: String,
+#[derive(Deserialize, Debug, Default)]
+pub struct WrappedMessage {
+    pub message: Option<WhatsAppMessage>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct MessageKey {
-    #[serde(rename = "remoteJid")]
-    pub remote_jid: String,
-    #[serde(rename = "fromMe")]
-    pub from_me: bool,
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextInfo {
+    #[serde(alias = "stanzaID")]
+    pub stanza_id: Option<String>,
+    pub participant: Option<String>,
+    pub quoted_message: Option<Box<WhatsAppMessage>>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtendedTextMessage {
+    pub text: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaMessage {
+    pub mimetype: Option<String>,
+    pub caption: Option<String>,
+    pub file_name: Option<String>,
+    pub title: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationMessage {
+    pub degrees_latitude: Option<f64>,
+    pub degrees_longitude: Option<f64>,
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactMessage {
+    pub display_name: Option<String>,
+    pub vcard: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ContactsArrayMessage {
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub contacts: Vec<ContactMessage>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReactionMessage {
+    pub key: Option<ReactionKey>,
+    pub text: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ReactionKey {
+    #[serde(alias = "ID")]
+    pub id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PollCreationMessage {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub options: Vec<PollOption>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PollOption {
+    pub option_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ButtonsResponseMessage {
+    #[serde(alias = "selectedButtonID")]
+    pub selected_button_id: Option<String>,
+    pub selected_display_text: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponseMessage {
+    pub title: Option<String>,
+    pub single_select_reply: Option<SingleSelectReply>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SingleSelectReply {
+    #[serde(alias = "selectedRowID")]
+    pub selected_row_id: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TemplateButtonReplyMessage {
+    #[serde(alias = "selectedID")]
+    pub selected_id: Option<String>,
+    pub selected_display_text: Option<String>,
+    pub context_info: Option<ContextInfo>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QuotedMessage {
     pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub participant: Option<String>,
+    pub text: String,
 }
 
-#[derive(Deserialize, Debug, Serialize)]
-pub struct MessageContent {
-    pub conversation: Option<String>,
+#[derive(Serialize, Debug, Clone)]
+pub struct NormalizedMessage {
+    pub id: String,
+    pub from: String,
+    pub to: String,
+    pub text: String,
+    pub body: String,
+    #[serde(rename = "type")]
+    pub msg_type: String,
+    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quoted: Option<QuotedMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<MediaReference>,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct SendMessageResponse {
-    pub status_code: Option<i32>,
-    pub status_string: Option<StatusString>,
+#[derive(Serialize, Debug, Clone)]
+pub struct MediaReference {
+    pub key: String,
+    pub url: String,
+    pub size: usize,
+    pub mime_type: String,
+    pub sha256: String,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct StatusString {
-    pub contextInfo: Option<serde_json::Value>,
-    pub instanceId: Option<String>,
-    pub key: Option<MessageKey>,
-    pub message: Option<MessageContent>,
-    pub messageTimestamp: Option<i64>,
-    pub messageType: Option<String>,
-    pub pushName: Option<String>,
-    pub source: Option<String>,
-    pub status: Option<String>,
+#[derive(Debug, Clone)]
+pub struct MediaFetch {
+    pub server_url: String,
+    pub instance: String,
+    pub apikey: String,
+    pub message_id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct IncomingMessage {
+    pub remote_jid: String,
+    pub instance_id: String,
+    pub message_id: String,
+    pub from_me: bool,
+    pub message: NormalizedMessage,
+    pub media_base64: Option<String>,
+    pub media_fetch: Option<MediaFetch>,
 }
@@ -1 +1,6 @@
-pub mod library;
\ No newline at end of file
+pub mod library;
+pub mod provider;
+pub mod content;
+pub mod status;
+pub mod evolution;
+pub mod wuzapi;
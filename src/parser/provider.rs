@@ -0,0 +1,55 @@
+use serde_json::Value;
+use crate::parser::library::IncomingMessage;
+use crate::parser::status::StatusUpdate;
+use crate::parser::{evolution, wuzapi};
+use crate::process::error::ProcessError;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Provider {
+    Evolution,
+    Wuzapi,
+}
+
+impl Provider {
+    pub fn from_name(name: &str) -> Option<Provider> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "evolution" => Some(Provider::Evolution),
+            "wuzapi" => Some(Provider::Wuzapi),
+            _ => None,
+        }
+    }
+
+    pub fn detect(value: &Value) -> Option<Provider> {
+        if evolution::matches(value) {
+            Some(Provider::Evolution)
+        } else if wuzapi::matches(value) {
+            Some(Provider::Wuzapi)
+        } else {
+            None
+        }
+    }
+
+    pub fn detect_status(value: &Value) -> Option<Provider> {
+        if evolution::matches_status(value) {
+            Some(Provider::Evolution)
+        } else if wuzapi::matches_status(value) {
+            Some(Provider::Wuzapi)
+        } else {
+            None
+        }
+    }
+
+    pub fn parse(&self, value: &Value) -> Result<Option<IncomingMessage>, ProcessError> {
+        match self {
+            Provider::Evolution => evolution::parse(value),
+            Provider::Wuzapi => wuzapi::parse(value),
+        }
+    }
+
+    pub fn parse_status(&self, value: &Value) -> Result<Vec<StatusUpdate>, ProcessError> {
+        match self {
+            Provider::Evolution => evolution::parse_status(value),
+            Provider::Wuzapi => wuzapi::parse_status(value),
+        }
+    }
+}
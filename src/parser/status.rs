@@ -0,0 +1,156 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MessageStatus {
+    Pending,
+    Sent,
+    Delivered,
+    Read,
+    Played,
+    Failed,
+}
+
+impl MessageStatus {
+    pub fn from_ack(value: &Value) -> Option<MessageStatus> {
+        if let Some(level) = value.as_i64() {
+            return match level {
+                0 => Some(MessageStatus::Failed),
+                1 => Some(MessageStatus::Pending),
+                2 => Some(MessageStatus::Sent),
+                3 => Some(MessageStatus::Delivered),
+                4 => Some(MessageStatus::Read),
+                5 => Some(MessageStatus::Played),
+                _ => None,
+            };
+        }
+        match value.as_str()?.to_ascii_uppercase().as_str() {
+            "ERROR" | "FAILED" => Some(MessageStatus::Failed),
+            "PENDING" => Some(MessageStatus::Pending),
+            "SERVER_ACK" | "SENT" => Some(MessageStatus::Sent),
+            "DELIVERY_ACK" | "DELIVERED" => Some(MessageStatus::Delivered),
+            "READ" => Some(MessageStatus::Read),
+            "PLAYED" => Some(MessageStatus::Played),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MessageStatus> {
+        match name {
+            "pending" => Some(MessageStatus::Pending),
+            "sent" => Some(MessageStatus::Sent),
+            "delivered" => Some(MessageStatus::Delivered),
+            "read" => Some(MessageStatus::Read),
+            "played" => Some(MessageStatus::Played),
+            "failed" => Some(MessageStatus::Failed),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageStatus::Pending => "pending",
+            MessageStatus::Sent => "sent",
+            MessageStatus::Delivered => "delivered",
+            MessageStatus::Read => "read",
+            MessageStatus::Played => "played",
+            MessageStatus::Failed => "failed",
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            MessageStatus::Pending => 0,
+            MessageStatus::Sent | MessageStatus::Failed => 1,
+            MessageStatus::Delivered => 2,
+            MessageStatus::Read => 3,
+            MessageStatus::Played => 4,
+        }
+    }
+
+    // Acks can arrive out of order (a late DELIVERY_ACK after READ), so the current status
+    // only ever moves forward; failures can't undo a delivery either.
+    pub fn supersedes(&self, current: Option<MessageStatus>) -> bool {
+        match current {
+            None => true,
+            Some(current) => self.rank() > current.rank(),
+        }
+    }
+
+    pub fn preceding(&self) -> Vec<&'static str> {
+        [
+            MessageStatus::Pending,
+            MessageStatus::Sent,
+            MessageStatus::Delivered,
+            MessageStatus::Read,
+            MessageStatus::Played,
+            MessageStatus::Failed,
+        ]
+        .into_iter()
+        .filter(|status| self.supersedes(Some(*status)))
+        .map(|status| status.as_str())
+        .collect()
+    }
+}
+
+#[derive(Debug)]
+pub struct StatusUpdate {
+    pub instance_id: String,
+    pub message_id: String,
+    pub remote_jid: String,
+    pub status: MessageStatus,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_numeric_and_named_acks() {
+        assert_eq!(MessageStatus::from_ack(&json!(3)), Some(MessageStatus::Delivered));
+        assert_eq!(MessageStatus::from_ack(&json!("delivery_ack")), Some(MessageStatus::Delivered));
+        assert_eq!(MessageStatus::from_ack(&json!("SERVER_ACK")), Some(MessageStatus::Sent));
+        assert_eq!(MessageStatus::from_ack(&json!(0)), Some(MessageStatus::Failed));
+        assert_eq!(MessageStatus::from_ack(&json!(9)), None);
+        assert_eq!(MessageStatus::from_ack(&json!("DELETED")), None);
+        assert_eq!(MessageStatus::from_ack(&json!(null)), None);
+    }
+
+    #[test]
+    fn only_moves_forward() {
+        assert!(MessageStatus::Pending.supersedes(None));
+        assert!(MessageStatus::Delivered.supersedes(Some(MessageStatus::Sent)));
+        assert!(MessageStatus::Played.supersedes(Some(MessageStatus::Read)));
+        assert!(!MessageStatus::Read.supersedes(Some(MessageStatus::Read)));
+        assert!(!MessageStatus::Sent.supersedes(Some(MessageStatus::Delivered)));
+    }
+
+    #[test]
+    fn ignores_late_acks_arriving_out_of_order() {
+        let arrivals = [MessageStatus::Sent, MessageStatus::Read, MessageStatus::Delivered, MessageStatus::Sent];
+        let mut current = None;
+        for status in arrivals {
+            if status.supersedes(current) {
+                current = Some(status);
+            }
+        }
+        assert_eq!(current, Some(MessageStatus::Read));
+    }
+
+    #[test]
+    fn failures_never_undo_a_delivery() {
+        assert!(MessageStatus::Failed.supersedes(Some(MessageStatus::Pending)));
+        assert!(!MessageStatus::Failed.supersedes(Some(MessageStatus::Sent)));
+        assert!(!MessageStatus::Failed.supersedes(Some(MessageStatus::Delivered)));
+        assert!(!MessageStatus::Sent.supersedes(Some(MessageStatus::Failed)));
+    }
+
+    #[test]
+    fn lists_the_statuses_an_update_may_replace() {
+        assert_eq!(MessageStatus::Delivered.preceding(), vec!["pending", "sent", "failed"]);
+        assert!(MessageStatus::Pending.preceding().is_empty());
+        assert_eq!(MessageStatus::Played.preceding(), vec!["pending", "sent", "delivered", "read", "failed"]);
+    }
+}
@@ -0,0 +1,124 @@
+use chrono::Utc;
+use serde_json::Value;
+use log::debug;
+use crate::parser::content::{normalize, parse_message, parse_timestamp};
+use crate::parser::library::IncomingMessage;
+use crate::parser::status::{MessageStatus, StatusUpdate};
+use crate::process::error::ProcessError;
+
+fn str_at<'a>(value: &'a Value, pointer: &str) -> &'a str {
+    value.pointer(pointer).and_then(|v| v.as_str()).unwrap_or("")
+}
+
+pub fn matches(value: &Value) -> bool {
+    value.pointer("/event/Info").is_some() || value.get("jsonData").is_some()
+}
+
+fn unwrap_json_data(value: &Value) -> Result<Value, ProcessError> {
+    match value.get("jsonData") {
+        Some(Value::String(raw)) => Ok(serde_json::from_str(raw)?),
+        Some(inner) => Ok(inner.clone()),
+        None => Ok(value.clone()),
+    }
+}
+
+// The instance is the Wuzapi instance name, or the user id on versions that don't send it. The user
+// token is a credential and must never end up in keys, URLs or events.
+fn instance_name(outer: &Value, inner: &Value) -> String {
+    ["instanceName", "userID"]
+        .iter()
+        .flat_map(|field| [outer.get(field), inner.get(field)])
+        .flatten()
+        .find_map(|v| v.as_str().filter(|s| !s.is_empty()).map(str::to_string).or_else(|| v.as_i64().map(|id| id.to_string())))
+        .unwrap_or_default()
+}
+
+pub fn parse(value: &Value) -> Result<Option<IncomingMessage>, ProcessError> {
+    let outer = value;
+    let value = unwrap_json_data(value)?;
+
+    let event_type = str_at(&value, "/type");
+    if event_type != "Message" {
+        debug!("Ignoring Wuzapi event of type {}", event_type);
+        return Ok(None);
+    }
+
+    let info = value.pointer("/event/Info")
+        .ok_or_else(|| ProcessError::Permanent("Wuzapi message without Info".to_string()))?;
+    let remote_jid = info.get("Chat")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ProcessError::Permanent("Wuzapi message without a Chat JID".to_string()))?;
+
+    let message = parse_message(value.pointer("/event/Message"));
+    let content = normalize(&message, value.get("base64").and_then(|v| v.as_str()), str_at(info, "/MediaType"));
+
+    let instance_id = instance_name(outer, &value);
+
+    Ok(Some(IncomingMessage {
+        remote_jid: remote_jid.to_string(),
+        instance_id,
+        message_id: str_at(info, "/ID").to_string(),
+        from_me: info.get("IsFromMe").and_then(|v| v.as_bool()).unwrap_or(false),
+        message: content.into_message(
+            format!("msg_{}", str_at(info, "/ID")),
+            str_at(info, "/Sender").to_string(),
+            remote_jid.to_string(),
+            str_at(info, "/Timestamp").to_string(),
+        ),
+        media_base64: value.get("base64").and_then(|v| v.as_str()).map(str::to_string),
+        media_fetch: None,
+    }))
+}
+
+pub fn matches_status(value: &Value) -> bool {
+    match unwrap_json_data(value) {
+        Ok(value) => str_at(&value, "/type") == "ReadReceipt",
+        Err(_) => false,
+    }
+}
+
+fn receipt_status(receipt_type: &str) -> Option<MessageStatus> {
+    match receipt_type {
+        "" | "delivered" => Some(MessageStatus::Delivered),
+        "sender" => Some(MessageStatus::Sent),
+        "read" | "read-self" => Some(MessageStatus::Read),
+        "played" | "played-self" => Some(MessageStatus::Played),
+        "server-error" => Some(MessageStatus::Failed),
+        _ => None,
+    }
+}
+
+pub fn parse_status(value: &Value) -> Result<Vec<StatusUpdate>, ProcessError> {
+    let outer = value;
+    let value = unwrap_json_data(value)?;
+    let event_type = str_at(&value, "/type");
+    if event_type != "ReadReceipt" {
+        debug!("Ignoring Wuzapi event of type {}", event_type);
+        return Ok(Vec::new());
+    }
+
+    let event = value.get("event")
+        .ok_or_else(|| ProcessError::Permanent("Wuzapi receipt without event".to_string()))?;
+    let receipt_type = str_at(event, "/Type");
+    let Some(status) = receipt_status(receipt_type) else {
+        debug!("Ignoring Wuzapi receipt of type {}", receipt_type);
+        return Ok(Vec::new());
+    };
+    let timestamp = parse_timestamp(str_at(event, "/Timestamp")).unwrap_or_else(Utc::now);
+    let remote_jid = str_at(event, "/Chat");
+    let instance_id = instance_name(outer, &value);
+
+    Ok(event.get("MessageIDs")
+        .and_then(|v| v.as_array())
+        .map(|ids| ids.iter().filter_map(|id| id.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|message_id| StatusUpdate {
+            instance_id: instance_id.clone(),
+            message_id: message_id.to_string(),
+            remote_jid: remote_jid.to_string(),
+            status,
+            timestamp,
+        })
+        .collect())
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+
+pub const USER_SERVER: &str = "s.whatsapp.net";
+pub const LEGACY_USER_SERVER: &str = "c.us";
+pub const GROUP_SERVER: &str = "g.us";
+pub const LID_SERVER: &str = "lid";
+pub const BROADCAST_SERVER: &str = "broadcast";
+pub const NEWSLETTER_SERVER: &str = "newsletter";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JidKind {
+    User,
+    Group,
+    Lid,
+    Broadcast,
+    Newsletter,
+    Unknown,
+}
+
+impl JidKind {
+    pub fn from_server(server: &str) -> Self {
+        match server {
+            USER_SERVER | LEGACY_USER_SERVER => JidKind::User,
+            GROUP_SERVER => JidKind::Group,
+            LID_SERVER => JidKind::Lid,
+            BROADCAST_SERVER => JidKind::Broadcast,
+            NEWSLETTER_SERVER => JidKind::Newsletter,
+            _ => JidKind::Unknown,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JidKind::User => "user",
+            JidKind::Group => "group",
+            JidKind::Lid => "lid",
+            JidKind::Broadcast => "broadcast",
+            JidKind::Newsletter => "newsletter",
+            JidKind::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Jid {
+    pub user: String,
+    pub device: Option<String>,
+    pub server: String,
+    pub kind: JidKind,
+}
+
+impl Jid {
+    // A bare value without a server (e.g. a CRM customer number) is treated as a user JID.
+    pub fn parse(raw: &str) -> Self {
+        let raw = raw.trim();
+        let (user, server) = match raw.split_once('@') {
+            Some((user, server)) => (user, server.to_ascii_lowercase()),
+            None => (raw, USER_SERVER.to_string()),
+        };
+        let (user, device) = match user.split_once(':') {
+            Some((user, device)) => (user, Some(device.to_string())),
+            None => (user, None),
+        };
+        let kind = JidKind::from_server(&server);
+        // Multi-device JIDs may carry an agent suffix (`user.0:12@...`), which isn't part of the identity.
+        let user = match kind {
+            JidKind::User | JidKind::Lid => user.split('.').next().unwrap_or(user),
+            _ => user,
+        };
+        let server = if kind == JidKind::User { USER_SERVER.to_string() } else { server };
+        Jid { user: user.to_string(), device, server, kind }
+    }
+
+    pub fn is_user(&self) -> bool {
+        self.kind == JidKind::User
+    }
+}
+
+impl fmt::Display for Jid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.user, self.server)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lid_jids() {
+        let jid = Jid::parse("123456789012345:7@lid");
+        assert_eq!(jid.kind, JidKind::Lid);
+        assert_eq!(jid.user, "123456789012345");
+        assert_eq!(jid.device.as_deref(), Some("7"));
+        assert_eq!(jid.to_string(), "123456789012345@lid");
+        assert!(!jid.is_user());
+    }
+
+    #[test]
+    fn parses_group_jids() {
+        let jid = Jid::parse("120363025246125486@g.us");
+        assert_eq!(jid.kind, JidKind::Group);
+        assert_eq!(jid.to_string(), "120363025246125486@g.us");
+
+        let legacy = Jid::parse("5511987654321-1600000000@g.us");
+        assert_eq!(legacy.kind, JidKind::Group);
+        assert_eq!(legacy.user, "5511987654321-1600000000");
+    }
+
+    #[test]
+    fn parses_newsletter_and_broadcast_jids() {
+        assert_eq!(Jid::parse("120363144038483540@newsletter").kind, JidKind::Newsletter);
+        assert_eq!(Jid::parse("status@broadcast").kind, JidKind::Broadcast);
+        assert_eq!(Jid::parse("foo@example.com").kind, JidKind::Unknown);
+    }
+
+    #[test]
+    fn strips_device_and_agent_from_user_jids() {
+        let jid = Jid::parse("5511987654321.0:12@s.whatsapp.net");
+        assert_eq!(jid.kind, JidKind::User);
+        assert_eq!(jid.user, "5511987654321");
+        assert_eq!(jid.device.as_deref(), Some("12"));
+        assert_eq!(jid.to_string(), "5511987654321@s.whatsapp.net");
+    }
+
+    #[test]
+    fn maps_legacy_and_bare_users_to_the_user_server() {
+        assert_eq!(Jid::parse("5511987654321@C.US").to_string(), "5511987654321@s.whatsapp.net");
+        let bare = Jid::parse(" 5511987654321 ");
+        assert!(bare.is_user());
+        assert_eq!(bare.to_string(), "5511987654321@s.whatsapp.net");
+    }
+}
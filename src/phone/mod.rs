@@ -0,0 +1,2 @@
+pub mod jid;
+pub mod normalize;
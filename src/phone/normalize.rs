@@ -0,0 +1,186 @@
+use log::info;
+use crate::config::config::PhoneConfig;
+use crate::phone::jid::{Jid, JidKind, USER_SERVER};
+
+const E164_MAX_DIGITS: usize = 15;
+
+pub trait CountryRule: Send + Sync {
+    fn calling_code(&self) -> &'static str;
+    // Whether a number typed without its country code looks like a national number of this country.
+    fn is_national(&self, national: &str) -> bool;
+    fn normalize_national(&self, national: &str) -> String;
+}
+
+pub struct BrazilRule;
+
+impl CountryRule for BrazilRule {
+    fn calling_code(&self) -> &'static str {
+        "55"
+    }
+
+    fn is_national(&self, national: &str) -> bool {
+        matches!(national.len(), 10 | 11) && !national.starts_with('0')
+    }
+
+    // Mobile numbers gained a leading 9 in 2016, but WhatsApp still reports many of them with the
+    // old 8 digit subscriber number. Landlines (starting with 2-5) never get the extra digit.
+    fn normalize_national(&self, national: &str) -> String {
+        if national.len() == 10 {
+            let (area_code, subscriber) = national.split_at(2);
+            if subscriber.starts_with(['6', '7', '8', '9']) {
+                return format!("{}9{}", area_code, subscriber);
+            }
+        }
+        national.to_string()
+    }
+}
+
+pub fn rule_by_name(name: &str) -> Option<Box<dyn CountryRule>> {
+    match name {
+        "br" => Some(Box::new(BrazilRule)),
+        _ => None,
+    }
+}
+
+pub struct PhoneNormalizer {
+    default_country: Option<String>,
+    rules: Vec<Box<dyn CountryRule>>,
+}
+
+impl PhoneNormalizer {
+    pub fn new(default_country: Option<String>, rules: Vec<Box<dyn CountryRule>>) -> Self {
+        PhoneNormalizer { default_country, rules }
+    }
+
+    pub fn from_config(config: &PhoneConfig) -> Result<Self, String> {
+        let rules = config.rules
+            .iter()
+            .map(|name| rule_by_name(name).ok_or_else(|| format!("Unknown phone rule '{}' in PHONE_COUNTRY_RULES", name)))
+            .collect::<Result<Vec<_>, _>>()?;
+        info!("Normalizing phone numbers with rules [{}]", config.rules.join(", "));
+        Ok(PhoneNormalizer::new(config.default_country.clone(), rules))
+    }
+
+    fn rule_for(&self, digits: &str) -> Option<&dyn CountryRule> {
+        self.rules
+            .iter()
+            .find(|rule| digits.starts_with(rule.calling_code()))
+            .map(|rule| rule.as_ref())
+    }
+
+    fn apply_rules(&self, digits: &str) -> Option<String> {
+        let normalized = match self.rule_for(digits) {
+            Some(rule) => {
+                let national = &digits[rule.calling_code().len()..];
+                format!("{}{}", rule.calling_code(), rule.normalize_national(national))
+            }
+            None => digits.to_string(),
+        };
+        (!normalized.is_empty() && normalized.len() <= E164_MAX_DIGITS).then_some(normalized)
+    }
+
+    // Numbers typed by people (CRM customers, contacts): may carry `+`, `00`, punctuation or lack the
+    // country code entirely. Returns E.164 digits without the leading `+`, as used in WhatsApp JIDs.
+    pub fn normalize_number(&self, raw: &str) -> Option<String> {
+        let trimmed = raw.trim();
+        let digits: String = trimmed.chars().filter(char::is_ascii_digit).collect();
+        if trimmed.starts_with('+') {
+            return self.apply_rules(&digits);
+        }
+        if let Some(international) = digits.strip_prefix("00") {
+            return self.apply_rules(international);
+        }
+        if let Some(code) = &self.default_country {
+            let national = digits.trim_start_matches('0');
+            if let Some(rule) = self.rules.iter().find(|rule| rule.calling_code() == code)
+                && rule.is_national(national) {
+                return self.apply_rules(&format!("{}{}", code, national));
+            }
+        }
+        self.apply_rules(&digits)
+    }
+
+    // JIDs reported by WhatsApp always carry the country code, so no national fallback applies here.
+    pub fn normalize_jid(&self, raw: &str) -> String {
+        if raw.trim().is_empty() {
+            return String::new();
+        }
+        let mut jid = Jid::parse(raw);
+        match jid.kind {
+            JidKind::User => {
+                let digits: String = jid.user.chars().filter(char::is_ascii_digit).collect();
+                if let Some(number) = self.apply_rules(&digits) {
+                    jid.user = number;
+                }
+                jid.to_string()
+            }
+            JidKind::Group | JidKind::Lid | JidKind::Broadcast | JidKind::Newsletter => jid.to_string(),
+            JidKind::Unknown => raw.trim().to_string(),
+        }
+    }
+
+    // Contacts coming from the CRM only carry a number, so it is read as a human typed one.
+    pub fn chat_id_for_number(&self, raw: &str) -> String {
+        if !raw.contains('@') && let Some(number) = self.normalize_number(raw) {
+            return format!("{}@{}", number, USER_SERVER);
+        }
+        self.normalize_jid(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brazil() -> PhoneNormalizer {
+        PhoneNormalizer::new(Some("55".to_string()), vec![Box::new(BrazilRule)])
+    }
+
+    #[test]
+    fn adds_the_ninth_digit_to_ten_digit_mobiles() {
+        let phone = brazil();
+        assert_eq!(phone.normalize_number("+55 (11) 8765-4321").as_deref(), Some("5511987654321"));
+        assert_eq!(phone.normalize_number("1187654321").as_deref(), Some("5511987654321"));
+        assert_eq!(phone.normalize_number("(21) 7654-3210").as_deref(), Some("5521976543210"));
+    }
+
+    #[test]
+    fn keeps_eleven_digit_mobiles() {
+        let phone = brazil();
+        assert_eq!(phone.normalize_number("11987654321").as_deref(), Some("5511987654321"));
+        assert_eq!(phone.normalize_number("+55 11 98765-4321").as_deref(), Some("5511987654321"));
+        assert_eq!(phone.normalize_number("5511987654321").as_deref(), Some("5511987654321"));
+    }
+
+    #[test]
+    fn keeps_ten_digit_landlines() {
+        let phone = brazil();
+        assert_eq!(phone.normalize_number("(11) 3265-4321").as_deref(), Some("551132654321"));
+        assert_eq!(phone.normalize_number("+55 51 2345-6789").as_deref(), Some("555123456789"));
+    }
+
+    #[test]
+    fn reads_international_prefixes() {
+        let phone = brazil();
+        assert_eq!(phone.normalize_number("0055 11 8765-4321").as_deref(), Some("5511987654321"));
+        assert_eq!(phone.normalize_number("011 98765-4321").as_deref(), Some("5511987654321"));
+        assert_eq!(phone.normalize_number("+1 (415) 555-0100").as_deref(), Some("14155550100"));
+    }
+
+    #[test]
+    fn rejects_numbers_that_cannot_be_e164() {
+        let phone = brazil();
+        assert_eq!(phone.normalize_number(""), None);
+        assert_eq!(phone.normalize_number("+1234567890123456"), None);
+    }
+
+    #[test]
+    fn only_normalizes_user_jids() {
+        let phone = brazil();
+        assert_eq!(phone.normalize_jid("551187654321@s.whatsapp.net"), "5511987654321@s.whatsapp.net");
+        assert_eq!(phone.normalize_jid("551187654321:3@c.us"), "5511987654321@s.whatsapp.net");
+        assert_eq!(phone.normalize_jid("551187654321@lid"), "551187654321@lid");
+        assert_eq!(phone.normalize_jid("551187654321-1600000000@g.us"), "551187654321-1600000000@g.us");
+        assert_eq!(phone.chat_id_for_number("(11) 8765-4321"), "5511987654321@s.whatsapp.net");
+    }
+}
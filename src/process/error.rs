@@ -0,0 +1,65 @@
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub enum ProcessError {
+    Transient(String),
+    Permanent(String),
+}
+
+impl ProcessError {
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ProcessError::Transient(_))
+    }
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::Transient(msg) => write!(f, "transient failure: {}", msg),
+            ProcessError::Permanent(msg) => write!(f, "permanent failure: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
+impl From<serde_json::Error> for ProcessError {
+    fn from(e: serde_json::Error) -> Self {
+        ProcessError::Permanent(format!("deserialization error: {}", e))
+    }
+}
+
+impl From<tokio_postgres::Error> for ProcessError {
+    // Errors without a SQLSTATE come from the connection itself. Data (22), constraint (23) and
+    // syntax/schema (42) errors fail the same way on every attempt, so they go straight to the DLQ.
+    fn from(e: tokio_postgres::Error) -> Self {
+        match e.code().map(|code| code.code()) {
+            Some(code) if code.starts_with("22") || code.starts_with("23") || code.starts_with("42") => {
+                ProcessError::Permanent(format!("database error: {}", e))
+            }
+            _ => ProcessError::Transient(format!("database error: {}", e)),
+        }
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for ProcessError {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        ProcessError::Transient(format!("database pool error: {}", e))
+    }
+}
+
+impl From<redis::RedisError> for ProcessError {
+    fn from(e: redis::RedisError) -> Self {
+        ProcessError::Transient(format!("redis error: {}", e))
+    }
+}
+
+impl From<reqwest::Error> for ProcessError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_builder() {
+            ProcessError::Permanent(format!("invalid request: {}", e))
+        } else {
+            ProcessError::Transient(format!("http error: {}", e))
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::future::Future;
+use log::{debug, error, info, warn};
+use crate::handlers::handler::Context;
+use crate::process::error::ProcessError;
+use crate::redis_mod::ledger::{claim, claim_state, count_duplicate, mark_done, processed_key, release, take_over, PROCESSING};
+
+pub const WHATSAPP_SCOPE: &str = "wa";
+pub const OPERATION_SCOPE: &str = "op";
+
+// The same WhatsApp message id shows up on every instance in a group, so the instance is part of the id.
+pub fn whatsapp_message_id(instance_id: &str, message_id: &str) -> String {
+    if message_id.is_empty() {
+        return String::new();
+    }
+    format!("{}:{}", instance_id, message_id)
+}
+
+// The id is claimed as `processing` with a short TTL and only marked `done` for DEDUP_TTL_SECS once the
+// side effects succeeded, so a handler that is dropped at the shutdown deadline or dies with the process
+// never leaves a message marked as handled. `redelivered` is the AMQP flag: a redelivery of a claimed id
+// means the previous holder gave the message back to the broker, so it takes the claim over.
+pub async fn process_once<F, Fut>(ctx: &Context, scope: &str, id: &str, redelivered: bool, process: F) -> Result<(), ProcessError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<(), ProcessError>>,
+{
+    if id.is_empty() {
+        debug!("No {} message id available, skipping deduplication", scope);
+        return process().await;
+    }
+
+    let key = processed_key(scope, id);
+    let mut redis_conn = ctx.redis.clone();
+    if !claim(&mut redis_conn, &key, ctx.env.dedup_processing_ttl).await? {
+        match claim_state(&mut redis_conn, &key).await?.as_deref() {
+            Some(PROCESSING) if redelivered => {
+                warn!("Taking over the claim on {} {} from an interrupted attempt", scope, id);
+                take_over(&mut redis_conn, &key, ctx.env.dedup_processing_ttl).await?;
+            }
+            Some(PROCESSING) => {
+                return Err(ProcessError::Transient(format!("{} message {} is still being processed", scope, id)));
+            }
+            // Expired between the two calls; let the broker hand it to us again.
+            None => return Err(ProcessError::Transient(format!("claim on {} {} changed, retrying", scope, id))),
+            Some(_) => {
+                match count_duplicate(&mut redis_conn, scope).await {
+                    Ok(total) => info!("Skipping duplicate {} message {} ({} duplicates so far)", scope, id, total),
+                    Err(e) => error!("Skipping duplicate {} message {}, but couldn't count it: {}", scope, id, e),
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    let result = process().await;
+    match &result {
+        Ok(_) => {
+            if let Err(e) = mark_done(&mut redis_conn, &key, ctx.env.dedup_ttl).await {
+                error!("Failed to mark {} as processed: {}", key, e);
+            }
+        }
+        Err(_) => {
+            if let Err(e) = release(&mut redis_conn, &key).await {
+                error!("Failed to release {} after a processing failure: {}", key, e);
+            }
+        }
+    }
+    result
+}
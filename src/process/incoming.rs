@@ -1,73 +1,103 @@
-use crate::redis_mod::redis::{insert_message_to_chat, normalize_chat_id};
-use redis::aio::MultiplexedConnection;
-use serde_json::{Value, json};
+use crate::redis_mod::redis::{ensure_chat_exists, to_fields, update_chat_fields};
+use crate::parser::provider::Provider;
+use redis::aio::ConnectionManager;
+use serde_json::Value;
+use log::{error, info, warn};
+use crate::process::error::ProcessError;
+use crate::handlers::handler::Context;
+use crate::media::offload::offload_media;
+use crate::process::persist::{normalize_addresses, persist_message, store_message};
+use crate::process::policy::{IngestionAction, IngestionCategory, Ingested};
+use crate::redis_mod::ledger::count_ingestion;
+use crate::process::idempotency::{process_once, whatsapp_message_id, WHATSAPP_SCOPE};
 
 
+fn is_contact(value: &Value) -> bool {
+    value.get("name").is_some() && value.get("number").is_some() && value.get("created_at").is_some()
+}
+
+async fn process_contact(value: &Value, redis_conn: &mut ConnectionManager, ctx: &Context) -> Result<(), ProcessError> {
+    let number = value.get("number").and_then(|v| v.as_str()).unwrap_or("unknown_chat");
+    let chat_id = ctx.phone.chat_id_for_number(number);
+
+    let mut contact = value.clone();
+    if let Some(normalized) = ctx.phone.normalize_number(number) {
+        contact["number"] = Value::String(normalized);
+    }
+    if contact.get("instance_id").is_none()
+        && let Some(instance_id) = value.pointer("/data/instanceId") {
+        contact["instance_id"] = instance_id.clone();
+    }
+    let instance_id = contact.get("instance_id").and_then(|v| v.as_str()).map(str::to_string);
+    ensure_chat_exists(redis_conn, &chat_id, &chat_id, instance_id.as_deref()).await?;
+    let fields: Vec<(String, String)> = to_fields(&contact)
+        .into_iter()
+        .map(|(field, value)| if field == "id" { ("contact_id".to_string(), value) } else { (field, value) })
+        .collect();
+    update_chat_fields(redis_conn, &chat_id, &fields).await?;
+    info!("Processed contact for chat {}", chat_id);
+    Ok(())
+}
+
 pub async fn process_incoming(
     data: &[u8],
-    redis_conn: &mut MultiplexedConnection,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    ctx: &Context,
+    provider: Option<Provider>,
+    routed: bool,
+    redelivered: bool,
+) -> Result<Ingested, ProcessError> {
     let value: Value = serde_json::from_slice(data)?;
+    let mut redis_conn = ctx.redis.clone();
 
-    let chat_id = value.pointer("/status_string/key/remote_jid")
-        .or_else(|| value.pointer("/data/key/remoteJid"))
-        .or_else(|| value.get("number"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("unknown_chat");
-    let chat_id = &normalize_chat_id(chat_id);
-    let remote_jid = chat_id;
-
-    let is_contact = value.get("name").is_some() && value.get("number").is_some() && value.get("created_at").is_some();
-    let chat_metadata_string;
-    let chat_metadata = if is_contact {
-        let mut contact = value.clone();
-        if contact.get("instance_id").is_none() {
-            if let Some(instance_id) = value.get("instance_id").or_else(|| value.get("data").and_then(|d| d.get("instanceId"))) {
-                contact["instance_id"] = instance_id.clone();
-            }
-        }
-        chat_metadata_string = serde_json::to_string(&contact).unwrap_or("".to_string());
-        Some(chat_metadata_string.as_str())
-    } else {
-        None
-    };
+    if is_contact(&value) {
+        process_contact(&value, &mut redis_conn, ctx).await?;
+        return Ok(Ingested::Handled);
+    }
 
-    let (msg_id, from, to, text, body, msg_type, timestamp) = if let Some(data) = value.get("data") {
-        let msg_id = data.pointer("/key/id").and_then(|v| v.as_str()).unwrap_or("");
-        let from = value.get("sender").and_then(|v| v.as_str()).unwrap_or("");
-        let to = data.pointer("/key/remoteJid").and_then(|v| v.as_str()).unwrap_or("");
-        let mut text = data.pointer("/message/conversation").and_then(|v| v.as_str()).unwrap_or("");
-        let mut body = text.to_string();
-        let mut msg_type = data.get("messageType").and_then(|v| v.as_str()).unwrap_or("");
-        let timestamp = value.get("date_time").and_then(|v| v.as_str()).unwrap_or("").to_string();
+    let provider = provider
+        .or_else(|| Provider::detect(&value))
+        .ok_or_else(|| ProcessError::Permanent("Couldn't detect the webhook provider for incoming message".to_string()))?;
 
-        if msg_type == "imageMessage" {
-            msg_type = "image";
-            let base64 = data.pointer("/message/base64").and_then(|v| v.as_str()).unwrap_or("");
-            body = format!("data:image/png;base64,{}", base64);
-            text = "📷 Imagem enviada";
-        } else if msg_type == "audioMessage" {
-            msg_type = "audio";
-            let base64 = data.pointer("/message/base64").and_then(|v| v.as_str()).unwrap_or("");
-            body = format!("data:audio/ogg;base64,{}", base64);
-            text = "Áudio enviado";
+    let mut incoming = match provider.parse(&value)? {
+        Some(incoming) => incoming,
+        None => {
+            warn!("Skipping {:?} webhook without a message", provider);
+            return Ok(Ingested::Handled);
         }
-        (msg_id.to_string(), from.to_string(), to.to_string(), text.to_string(), body.to_string(), msg_type.to_string(), timestamp)
-    } else {
-        ("".to_string(), "".to_string(), "".to_string(), "".to_string(), "".to_string(), "".to_string(), "".to_string())
     };
-    let normalized = json!({
-        "id": format!("msg_{}", msg_id),
-        "from": from,
-        "to": to,
-        "text": text,
-        "body": body,
-        "type": msg_type,
-        "timestamp": timestamp
-    });
-    let message_json = serde_json::to_string(&normalized).unwrap_or_default();
-    insert_message_to_chat(redis_conn, chat_id, &message_json, remote_jid, chat_metadata, Some(data)).await?;
 
+    // Messages routed here by another consumer already went through the policy once.
+    let category = IngestionCategory::classify(&incoming).filter(|_| !routed);
+    let action = match category {
+        Some(category) => ctx.env.ingestion.policy_for(&incoming.instance_id).action_for(category).clone(),
+        None => IngestionAction::Process,
+    };
+    if let Some(category) = category
+        && action != IngestionAction::Process {
+        info!("Applying {} policy to {} message {} from {}", action.as_str(), category.as_str(), incoming.message_id, incoming.remote_jid);
+        if let Err(e) = count_ingestion(&mut redis_conn, &incoming.instance_id, category.as_str(), action.as_str()).await {
+            error!("Couldn't count filtered {} message: {}", category.as_str(), e);
+        }
+    }
 
-    Ok(())
+    normalize_addresses(&mut incoming, &ctx.phone);
+    let message_id = whatsapp_message_id(&incoming.instance_id, &incoming.message_id);
+    match action {
+        IngestionAction::Drop => Ok(Ingested::Handled),
+        IngestionAction::Route(queue) => Ok(Ingested::Route(queue)),
+        IngestionAction::Store => {
+            process_once(ctx, WHATSAPP_SCOPE, &message_id, redelivered, || async {
+                offload_media(&mut incoming, ctx).await?;
+                store_message(&incoming, ctx).await.map(|_| ())
+            }).await?;
+            Ok(Ingested::Handled)
+        }
+        IngestionAction::Process => {
+            process_once(ctx, WHATSAPP_SCOPE, &message_id, redelivered, || async {
+                offload_media(&mut incoming, ctx).await?;
+                persist_message(&incoming, ctx).await
+            }).await?;
+            Ok(Ingested::Handled)
+        }
+    }
 }
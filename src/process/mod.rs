@@ -1,2 +1,10 @@
 pub mod outgoing;
 pub mod incoming;
+pub mod sent;
+pub mod status;
+pub mod persist;
+pub mod idempotency;
+pub mod error;
+pub mod policy;
+pub mod reply;
+pub mod ratelimit;
@@ -1,103 +1,255 @@
-use tokio_postgres::Client;
-use log::{error, info, debug};
+use log::{error, info, debug, warn};
+use std::time::Instant;
+use serde_json::Value;
+use crate::api::provider::{provider_for, WhatsAppProvider};
+use crate::parser::library::{Chat, Customer, Envelope, Message, Operation, Request, ENVELOPE_VERSION};
+use crate::process::error::ProcessError;
+use crate::handlers::handler::Context;
+use crate::process::ratelimit::throttle;
+use crate::process::reply::{publish_result, result_event, ReplyTarget};
 
-pub async fn process_outgoing(data: &[u8], client: &Client) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn process_outgoing(data: &[u8], ctx: &Context, reply: &ReplyTarget) -> Result<(), ProcessError> {
     let request_text = String::from_utf8_lossy(data);
-    
+
     debug!("Received message: {}", request_text);
     info!("Processing message of {} bytes", data.len());
-    
-    if request_text.contains("upsertChat") {
-        info!("Starting UpsertChat process...");
-        match serde_json::from_str::<crate::parser::library::Chat>(&request_text) {
-            Ok(chat) => {
-                info!("Successfully deserialized chat with ID: {}", chat.id);
-                match crate::database::insert::upsert_chats(&client, &chat).await {
-                    Ok(_) => {
-                        info!("Succesfully upserted chat into the db!");
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        error!("Error on upserting chat into the db: {}",e);
-                        return Err("Couldn't upsert data into the db.".into());
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to deserialize chat from JSON: {}", e);
-                error!("Raw message: {}", request_text);
-                return Err(format!("Couldn't deserialize chat data: {}", e).into());
-            }
+
+    let operation = match parse_operation(&request_text, ctx.env.accept_legacy_payloads) {
+        Ok(operation) => operation,
+        Err(e) => {
+            error!("Failed to parse outgoing message: {}", e);
+            error!("Raw message: {}", request_text);
+            return Err(e);
         }
-    } else if request_text.contains("upsertCustomer") {
-        info!("Starting UpsertCustomer process...");
-        match serde_json::from_str::<crate::parser::library::Customer>(&request_text) {
-            Ok(customer) => {
-                info!("Successfully deserialized customer with ID: {}", customer.id);
-                match crate::database::insert::upsert_customer(&client, &customer).await {
-                    Ok(_) => {
-                        info!("Succesfully upserted customer into the db!");
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        error!("Error on upserting customer into the db: {}",e);
-                        return Err("Couldn't upsert customer into the db.".into());
-                    }
+    };
+
+    dispatch(operation, ctx, reply).await
+}
+
+fn parse_operation(request_text: &str, accept_legacy: bool) -> Result<Operation, ProcessError> {
+    let value: Value = serde_json::from_str(request_text)
+        .map_err(|e| ProcessError::Permanent(format!("Couldn't deserialize data - invalid JSON: {}", e)))?;
+
+    if value.get("type").is_some() && value.get("payload").is_some() {
+        let envelope: Envelope = serde_json::from_value(value)
+            .map_err(|e| ProcessError::Permanent(format!("Couldn't deserialize envelope: {}", e)))?;
+        if envelope.version != ENVELOPE_VERSION {
+            return Err(ProcessError::Permanent(format!("Unsupported envelope version: {}", envelope.version)));
+        }
+        return Ok(envelope.operation);
+    }
+
+    if !accept_legacy {
+        return Err(ProcessError::Permanent("Couldn't deserialize data - message is not a typed envelope and legacy payloads are disabled.".to_string()));
+    }
+
+    warn!("Received legacy untagged payload, detecting its type from its fields");
+    parse_legacy(value)
+}
+
+// Legacy payloads are the bare structs. Their required fields don't overlap, so the first one that
+// deserializes is the type that was sent.
+fn parse_legacy(value: Value) -> Result<Operation, ProcessError> {
+    if let Ok(request) = serde_json::from_value::<Request>(value.clone()) {
+        return Ok(Operation::SendRequest(request));
+    }
+    if let Ok(message) = serde_json::from_value::<Message>(value.clone()) {
+        return Ok(Operation::UpsertMessage(message));
+    }
+    if let Ok(chat) = serde_json::from_value::<Chat>(value.clone()) {
+        return Ok(Operation::UpsertChat(chat));
+    }
+    if let Ok(customer) = serde_json::from_value::<Customer>(value) {
+        return Ok(Operation::UpsertCustomer(customer));
+    }
+    error!("Legacy payload doesn't match any of Chat, Customer, Message or Request");
+    Err(ProcessError::Permanent("Couldn't deserialize data - unknown message type.".to_string()))
+}
+
+async fn dispatch(operation: Operation, ctx: &Context, reply: &ReplyTarget) -> Result<(), ProcessError> {
+    match operation {
+        Operation::UpsertChat(chat) => {
+            info!("Starting UpsertChat process for chat with ID: {}", chat.id);
+            let client = ctx.db.get().await?;
+            match crate::database::insert::upsert_chats(&client, &chat).await {
+                Ok(_) => {
+                    info!("Succesfully upserted chat into the db!");
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("Error on upserting chat into the db: {}",e);
+                    Err(e.into())
                 }
-            }
-            Err(e) => {
-                error!("Failed to deserialize customer from JSON: {}", e);
-                error!("Raw message: {}", request_text);
-                return Err(format!("Couldn't deserialize customer data: {}", e).into());
             }
         }
-    } else if request_text.contains("SendMessage") {
-        info!("Starting UpsertMessage process...");
-        match serde_json::from_str::<crate::parser::library::Message>(&request_text) {
-            Ok(message) => {
-                info!("Successfully deserialized message with ID: {}", message.id);
-                match crate::database::insert::upsert_messages(&client, &message).await {
-                    Ok(_) => {
-                        info!("Succesfully upserted message into the db!");
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        error!("Error on upserting message into the db: {}",e);
-                        return Err("Couldn't upsert message into the db.".into());
-                    }
-                }
+        Operation::UpsertCustomer(mut customer) => {
+            info!("Starting UpsertCustomer process for customer with ID: {}", customer.id);
+            match ctx.phone.normalize_number(&customer.number) {
+                Some(number) => customer.number = number,
+                None => warn!("Couldn't normalize number of customer {}, storing it as is", customer.id),
             }
-            Err(e) => {
-                error!("Failed to deserialize message from JSON: {}", e);
-                error!("Raw message: {}", request_text);
-                return Err(format!("Couldn't deserialize message data: {}", e).into());
+            let client = ctx.db.get().await?;
+            match crate::database::insert::upsert_customer(&client, &customer).await {
+                Ok(_) => {
+                    info!("Succesfully upserted customer into the db!");
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("Error on upserting customer into the db: {}",e);
+                    Err(e.into())
+                }
             }
         }
-    } else if request_text.contains("sendRequest") {
-        info!("Starting SendRequest process...");
-        match serde_json::from_str::<crate::parser::library::Request>(&request_text) {
-            Ok(request) => {
-                info!("Successfully deserialized request for: {}", request.action);
-                match crate::api::requests::make_request(request).await {
-                    Ok(_) => {
-                        info!("Succesfully processed the request!");
-                        return Ok(());
-                    }
-                    Err(e) => {
-                        error!("Error on processing request: {}",e);
-                        return Err("Couldn't process request.".into());
-                    }
+        Operation::UpsertMessage(message) => {
+            info!("Starting UpsertMessage process for message with ID: {}", message.id);
+            let client = ctx.db.get().await?;
+            match crate::database::insert::upsert_messages(&client, &message).await {
+                Ok(_) => {
+                    info!("Succesfully upserted message into the db!");
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("Error on upserting message into the db: {}",e);
+                    Err(e.into())
                 }
             }
-            Err(e) => {
-                error!("Failed to deserialize request from JSON: {}", e);
-                error!("Raw message: {}", request_text);
-                return Err(format!("Couldn't deserialize request data: {}", e).into());
+        }
+        Operation::SendRequest(request) => {
+            if let Some(instance) = &request.instance {
+                throttle(ctx, instance, None).await?;
             }
+            run_request(ctx, reply, request).await
         }
-    } else {
-        error!("Unknown message type. Message content: {}", request_text);
-        error!("Message doesn't contain any of the expected keywords: UpsertChat, UpsertCustomer, UpsertMessage, SendRequest");
-        return Err("Couldn't deserialize data - unknown message type.".into());
+        Operation::SendText(op) => send(ctx, reply, &op.target.instance, Some(&op.target.number), &op.target.correlation_id, |p| p.send_text(&op)).await,
+        Operation::SendMedia(op) => send(ctx, reply, &op.target.instance, Some(&op.target.number), &op.target.correlation_id, |p| p.send_media(&op)).await,
+        Operation::SendAudio(op) => send(ctx, reply, &op.target.instance, Some(&op.target.number), &op.target.correlation_id, |p| p.send_audio(&op)).await,
+        Operation::SendLocation(op) => send(ctx, reply, &op.target.instance, Some(&op.target.number), &op.target.correlation_id, |p| p.send_location(&op)).await,
+        Operation::SendContact(op) => send(ctx, reply, &op.target.instance, Some(&op.target.number), &op.target.correlation_id, |p| p.send_contact(&op)).await,
+        Operation::SendReaction(op) => send(ctx, reply, &op.target.instance, Some(&op.target.number), &op.target.correlation_id, |p| p.send_reaction(&op)).await,
+        Operation::SendPresence(op) => send(ctx, reply, &op.target.instance, None, &op.target.correlation_id, |p| p.send_presence(&op)).await,
+        Operation::MarkMessageAsRead(op) => send(ctx, reply, &op.target.instance, None, &op.target.correlation_id, |p| p.mark_message_as_read(&op)).await,
+        Operation::DeleteMessage(op) => send(ctx, reply, &op.target.instance, None, &op.target.correlation_id, |p| p.delete_message(&op)).await,
+        Operation::CheckWhatsAppNumbers(op) => send(ctx, reply, &op.instance, None, &op.correlation_id, |p| p.check_whatsapp_numbers(&op)).await,
+    }
+}
+
+// Sends a logical message through whichever provider backs the instance. Messages to a number
+// first go through the rate limits of the instance.
+async fn send<F>(
+    ctx: &Context,
+    reply: &ReplyTarget,
+    instance: &str,
+    number: Option<&str>,
+    correlation_id: &Option<String>,
+    build: F,
+) -> Result<(), ProcessError>
+where
+    F: FnOnce(&dyn WhatsAppProvider) -> Result<Request, ProcessError>,
+{
+    let mut request = build(provider_for(&ctx.env, &ctx.phone, instance)?.as_ref())?;
+    request.correlation_id = correlation_id.clone();
+    if number.is_some() {
+        throttle(ctx, instance, number).await?;
+    }
+    run_request(ctx, reply, request).await
+}
+
+async fn run_request(ctx: &Context, reply: &ReplyTarget, request: Request) -> Result<(), ProcessError> {
+    let correlation_id = reply.correlation_id_for(&request);
+    info!("Starting {} process ({})", request.action, correlation_id);
+    let started = Instant::now();
+    let result = crate::api::requests::make_request(&ctx.http, &request).await;
+    let event = result_event(&request, &correlation_id, reply.attempt, started.elapsed(), &result);
+    publish_result(ctx, reply, &correlation_id, &event).await;
+    match result.and_then(|response| response.error().map_or(Ok(()), Err)) {
+        Ok(_) => {
+            info!("Succesfully processed the request!");
+            Ok(())
+        }
+        Err(e) => {
+            error!("Error on processing request: {}",e);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chat() -> Value {
+        json!({ "id": 123, "situation": "active", "is_active": true, "agent_id": 456, "tabulation": "support", "customer_id": 789 })
+    }
+
+    fn customer() -> Value {
+        json!({ "id": 789, "name": "João Silva", "number": "5511999999999", "last_chat_id": "chat_123" })
+    }
+
+    fn message() -> Value {
+        json!({ "id": 456, "from": "5511999999999", "to": "5511888888888", "delivered": true, "text": "Olá!", "chat_id": 123 })
+    }
+
+    fn request() -> Value {
+        json!({ "action": "send_message", "method": "POST", "url": "https://api.example.com/send", "headers": {} })
+    }
+
+    fn parse(value: &Value, accept_legacy: bool) -> Result<Operation, ProcessError> {
+        parse_operation(&value.to_string(), accept_legacy)
+    }
+
+    #[test]
+    fn parses_envelopes() {
+        let envelope = json!({ "type": "upsertChat", "version": 1, "payload": chat() });
+        assert!(matches!(parse(&envelope, false), Ok(Operation::UpsertChat(chat)) if chat.id == 123));
+
+        let alias = json!({ "type": "sendMessage", "version": 1, "payload": message() });
+        assert!(matches!(parse(&alias, false), Ok(Operation::UpsertMessage(_))));
+    }
+
+    #[test]
+    fn rejects_bad_envelopes() {
+        let version = json!({ "type": "upsertChat", "version": 2, "payload": chat() });
+        assert!(matches!(parse(&version, true), Err(ProcessError::Permanent(_))));
+
+        let mismatched = json!({ "type": "upsertChat", "version": 1, "payload": customer() });
+        assert!(matches!(parse(&mismatched, true), Err(ProcessError::Permanent(_))));
+
+        assert!(matches!(parse_operation("not json", true), Err(ProcessError::Permanent(_))));
+    }
+
+    #[test]
+    fn detects_each_legacy_payload() {
+        assert!(matches!(parse(&request(), true), Ok(Operation::SendRequest(_))));
+        assert!(matches!(parse(&message(), true), Ok(Operation::UpsertMessage(_))));
+        assert!(matches!(parse(&chat(), true), Ok(Operation::UpsertChat(_))));
+        assert!(matches!(parse(&customer(), true), Ok(Operation::UpsertCustomer(_))));
+    }
+
+    #[test]
+    fn detects_camel_case_legacy_payloads() {
+        let chat = json!({ "id": 1, "situation": "active", "isActive": false, "customerId": 2 });
+        assert!(matches!(parse(&chat, true), Ok(Operation::UpsertChat(chat)) if chat.customer_id == 2));
+    }
+
+    // Detection order only matters if a payload could fit two structs; each one must fit exactly one.
+    #[test]
+    fn legacy_required_fields_do_not_overlap() {
+        for (payload, own) in [(request(), 0), (message(), 1), (chat(), 2), (customer(), 3)] {
+            let fits = [
+                serde_json::from_value::<Request>(payload.clone()).is_ok(),
+                serde_json::from_value::<Message>(payload.clone()).is_ok(),
+                serde_json::from_value::<Chat>(payload.clone()).is_ok(),
+                serde_json::from_value::<Customer>(payload.clone()).is_ok(),
+            ];
+            let expected: Vec<bool> = (0..4).map(|i| i == own).collect();
+            assert_eq!(fits.to_vec(), expected, "payload {}", payload);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_or_disabled_legacy_payloads() {
+        assert!(matches!(parse(&json!({ "id": 1 }), true), Err(ProcessError::Permanent(_))));
+        assert!(matches!(parse(&chat(), false), Err(ProcessError::Permanent(_))));
     }
 }
@@ -0,0 +1,30 @@
+use log::info;
+use crate::database::insert::upsert_wa_message;
+use crate::events::publish::publish_message;
+use crate::handlers::handler::Context;
+use crate::parser::library::IncomingMessage;
+use crate::phone::normalize::PhoneNormalizer;
+use crate::process::error::ProcessError;
+use crate::redis_mod::redis::insert_message_to_chat;
+
+pub fn normalize_addresses(record: &mut IncomingMessage, phone: &PhoneNormalizer) {
+    record.message.from = phone.normalize_jid(&record.message.from);
+    record.message.to = phone.normalize_jid(&record.message.to);
+}
+
+pub async fn store_message(record: &IncomingMessage, ctx: &Context) -> Result<String, ProcessError> {
+    let chat_id = ctx.phone.normalize_jid(&record.remote_jid);
+    let client = ctx.db.get().await?;
+    upsert_wa_message(&client, &chat_id, record).await?;
+    info!("Stored message {} for chat {} in the db", record.message_id, chat_id);
+    Ok(chat_id)
+}
+
+pub async fn persist_message(record: &IncomingMessage, ctx: &Context) -> Result<(), ProcessError> {
+    let chat_id = store_message(record, ctx).await?;
+
+    let mut redis_conn = ctx.redis.clone();
+    let chat_created = insert_message_to_chat(&mut redis_conn, &chat_id, &record.message, &record.instance_id, record.from_me).await?;
+    publish_message(&mut redis_conn, &chat_id, &record.instance_id, &record.message, record.from_me, chat_created).await;
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use crate::config::config::queue_env_key;
+use crate::parser::library::IncomingMessage;
+use crate::phone::jid::{Jid, JidKind};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IngestionAction {
+    Process,
+    Drop,
+    Store,
+    Route(String),
+}
+
+impl IngestionAction {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim() {
+            "process" => Ok(IngestionAction::Process),
+            "drop" => Ok(IngestionAction::Drop),
+            "store" => Ok(IngestionAction::Store),
+            other => match other.strip_prefix("route:").map(str::trim) {
+                Some(queue) if !queue.is_empty() => Ok(IngestionAction::Route(queue.to_string())),
+                _ => Err(format!("Invalid ingestion action '{}', expected process, drop, store or route:<queue>", other)),
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IngestionAction::Process => "process",
+            IngestionAction::Drop => "drop",
+            IngestionAction::Store => "store",
+            IngestionAction::Route(_) => "route",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IngestionCategory {
+    Broadcast,
+    Newsletter,
+    Group,
+    FromMe,
+}
+
+impl IngestionCategory {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim() {
+            "broadcast" => Some(IngestionCategory::Broadcast),
+            "newsletter" => Some(IngestionCategory::Newsletter),
+            "group" => Some(IngestionCategory::Group),
+            "from_me" => Some(IngestionCategory::FromMe),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IngestionCategory::Broadcast => "broadcast",
+            IngestionCategory::Newsletter => "newsletter",
+            IngestionCategory::Group => "group",
+            IngestionCategory::FromMe => "from_me",
+        }
+    }
+
+    // The chat kind wins over fromMe: a message we sent to a group is still group traffic.
+    pub fn classify(message: &IncomingMessage) -> Option<Self> {
+        match Jid::parse(&message.remote_jid).kind {
+            JidKind::Broadcast => Some(IngestionCategory::Broadcast),
+            JidKind::Newsletter => Some(IngestionCategory::Newsletter),
+            JidKind::Group => Some(IngestionCategory::Group),
+            _ if message.from_me => Some(IngestionCategory::FromMe),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IngestionPolicy {
+    pub broadcast: IngestionAction,
+    pub newsletter: IngestionAction,
+    pub group: IngestionAction,
+    pub from_me: IngestionAction,
+}
+
+impl Default for IngestionPolicy {
+    fn default() -> Self {
+        IngestionPolicy {
+            broadcast: IngestionAction::Drop,
+            newsletter: IngestionAction::Drop,
+            group: IngestionAction::Process,
+            from_me: IngestionAction::Process,
+        }
+    }
+}
+
+impl IngestionPolicy {
+    pub fn action_for(&self, category: IngestionCategory) -> &IngestionAction {
+        match category {
+            IngestionCategory::Broadcast => &self.broadcast,
+            IngestionCategory::Newsletter => &self.newsletter,
+            IngestionCategory::Group => &self.group,
+            IngestionCategory::FromMe => &self.from_me,
+        }
+    }
+
+    // Applies a `category=action,...` spec on top of this policy, so overrides only list what changes.
+    pub fn with_spec(&self, spec: &str) -> Result<Self, String> {
+        let mut policy = self.clone();
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (category, action) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid ingestion policy entry '{}', expected category=action", entry))?;
+            let category = IngestionCategory::from_name(category)
+                .ok_or_else(|| format!("Unknown ingestion category '{}', expected broadcast, newsletter, group or from_me", category.trim()))?;
+            let action = IngestionAction::parse(action)?;
+            match category {
+                IngestionCategory::Broadcast => policy.broadcast = action,
+                IngestionCategory::Newsletter => policy.newsletter = action,
+                IngestionCategory::Group => policy.group = action,
+                IngestionCategory::FromMe => policy.from_me = action,
+            }
+        }
+        Ok(policy)
+    }
+
+    fn actions(&self) -> [&IngestionAction; 4] {
+        [&self.broadcast, &self.newsletter, &self.group, &self.from_me]
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct IngestionConfig {
+    pub default: IngestionPolicy,
+    // Keyed by the env key of the instance (see `queue_env_key`), as that is how overrides are named.
+    pub instances: HashMap<String, IngestionPolicy>,
+}
+
+impl IngestionConfig {
+    pub fn policy_for(&self, instance_id: &str) -> &IngestionPolicy {
+        self.instances.get(&queue_env_key(instance_id)).unwrap_or(&self.default)
+    }
+
+    pub fn route_queues(&self) -> Vec<String> {
+        let mut queues: Vec<String> = std::iter::once(&self.default)
+            .chain(self.instances.values())
+            .flat_map(IngestionPolicy::actions)
+            .filter_map(|action| match action {
+                IngestionAction::Route(queue) => Some(queue.clone()),
+                _ => None,
+            })
+            .collect();
+        queues.sort();
+        queues.dedup();
+        queues
+    }
+}
+
+pub enum Ingested {
+    Handled,
+    Route(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_only_the_listed_categories() {
+        let policy = IngestionPolicy::default().with_spec("group=route:wa.groups, from_me=drop,").unwrap();
+        assert_eq!(policy.broadcast, IngestionAction::Drop);
+        assert_eq!(policy.newsletter, IngestionAction::Drop);
+        assert_eq!(policy.group, IngestionAction::Route("wa.groups".to_string()));
+        assert_eq!(policy.from_me, IngestionAction::Drop);
+    }
+
+    #[test]
+    fn accepts_an_empty_spec() {
+        let policy = IngestionPolicy::default().with_spec("").unwrap();
+        assert_eq!(policy.group, IngestionAction::Process);
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        let policy = IngestionPolicy::default();
+        assert!(policy.with_spec("group").is_err());
+        assert!(policy.with_spec("groups=drop").is_err());
+        assert!(policy.with_spec("group=ignore").is_err());
+        assert!(policy.with_spec("group=route:").is_err());
+        assert!(policy.with_spec("group=route: ").is_err());
+        assert!(policy.with_spec("group=drop,broadcast").is_err());
+    }
+
+    #[test]
+    fn collects_route_queues_once() {
+        let default = IngestionPolicy::default().with_spec("group=route:wa.groups").unwrap();
+        let instance = default.with_spec("newsletter=route:wa.groups,broadcast=route:wa.status").unwrap();
+        let config = IngestionConfig {
+            default,
+            instances: HashMap::from([(queue_env_key("minha-instancia"), instance)]),
+        };
+        assert_eq!(config.route_queues(), vec!["wa.groups".to_string(), "wa.status".to_string()]);
+        assert_eq!(config.policy_for("minha-instancia").broadcast, IngestionAction::Route("wa.status".to_string()));
+        assert_eq!(config.policy_for("outra").broadcast, IngestionAction::Drop);
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use log::{debug, info};
+use rand::Rng;
+use tokio::time::sleep;
+use crate::api::provider::recipient;
+use crate::config::config::queue_env_key;
+use crate::handlers::handler::Context;
+use crate::process::error::ProcessError;
+use crate::redis_mod::ratelimit::{instance_bucket_key, number_bucket_key, take_tokens};
+
+// Longest a worker waits for tokens before handing the message to the retry queues, well below
+// RabbitMQ's consumer_timeout for the unacked delivery.
+pub const DEFAULT_MAX_WAIT_MS: u64 = 30_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TokenBucket {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+impl TokenBucket {
+    // Accepts `<count>/<s|min|h>`, e.g. `20/min`.
+    fn parse_rate(value: &str) -> Result<f64, String> {
+        let (count, unit) = value
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| format!("Invalid rate '{}', expected <count>/<s|min|h>", value.trim()))?;
+        let count: f64 = count
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid rate count '{}'", count.trim()))?;
+        let seconds = match unit.trim() {
+            "s" => 1.0,
+            "min" => 60.0,
+            "h" => 3600.0,
+            other => return Err(format!("Invalid rate unit '{}', expected s, min or h", other)),
+        };
+        if count <= 0.0 {
+            return Err(format!("Invalid rate '{}', the count must be positive", value.trim()));
+        }
+        Ok(count / seconds)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitPolicy {
+    pub instance: Option<TokenBucket>,
+    pub number: Option<TokenBucket>,
+    // Random pause in milliseconds before each send, so bursts don't look automated.
+    pub delay: Option<RangeInclusive<u64>>,
+    // Milliseconds to wait for tokens before retrying later, `DEFAULT_MAX_WAIT_MS` when unset.
+    pub max_wait: Option<u64>,
+}
+
+fn parse_bucket(current: Option<TokenBucket>, value: &str) -> Result<Option<TokenBucket>, String> {
+    if value.trim() == "off" {
+        return Ok(None);
+    }
+    let per_second = TokenBucket::parse_rate(value)?;
+    Ok(Some(TokenBucket { per_second, burst: current.map_or(1, |bucket| bucket.burst) }))
+}
+
+fn parse_burst(current: Option<TokenBucket>, key: &str, value: &str) -> Result<Option<TokenBucket>, String> {
+    let burst: u32 = value
+        .trim()
+        .parse()
+        .ok()
+        .filter(|burst| *burst > 0)
+        .ok_or_else(|| format!("Invalid {} '{}', expected a positive integer", key, value.trim()))?;
+    let bucket = current.ok_or_else(|| format!("{} needs a rate for the same bucket first", key))?;
+    Ok(Some(TokenBucket { burst, ..bucket }))
+}
+
+fn parse_delay(value: &str) -> Result<Option<RangeInclusive<u64>>, String> {
+    let value = value.trim();
+    if value == "off" {
+        return Ok(None);
+    }
+    let (min, max) = value.split_once('-').unwrap_or((value, value));
+    let parse = |part: &str| part.trim().parse::<u64>().map_err(|_| format!("Invalid delay '{}', expected <ms> or <min_ms>-<max_ms>", value));
+    let (min, max) = (parse(min)?, parse(max)?);
+    if min > max {
+        return Err(format!("Invalid delay '{}', the minimum is above the maximum", value));
+    }
+    Ok(Some(min..=max))
+}
+
+fn parse_max_wait(value: &str) -> Result<Option<u64>, String> {
+    value
+        .trim()
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("Invalid max_wait '{}', expected milliseconds", value.trim()))
+}
+
+impl RateLimitPolicy {
+    // Applies a `key=value,...` spec on top of this policy, so overrides only list what changes.
+    pub fn with_spec(&self, spec: &str) -> Result<Self, String> {
+        let mut policy = self.clone();
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (key, value) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid rate limit entry '{}', expected key=value", entry))?;
+            match key.trim() {
+                "instance" => policy.instance = parse_bucket(policy.instance, value)?,
+                "instance_burst" => policy.instance = parse_burst(policy.instance, "instance_burst", value)?,
+                "number" => policy.number = parse_bucket(policy.number, value)?,
+                "number_burst" => policy.number = parse_burst(policy.number, "number_burst", value)?,
+                "delay" => policy.delay = parse_delay(value)?,
+                "max_wait" => policy.max_wait = parse_max_wait(value)?,
+                other => return Err(format!("Unknown rate limit key '{}', expected instance, instance_burst, number, number_burst, delay or max_wait", other)),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitConfig {
+    pub default: RateLimitPolicy,
+    // Keyed by the env key of the instance (see `queue_env_key`), as that is how overrides are named.
+    pub instances: HashMap<String, RateLimitPolicy>,
+}
+
+impl RateLimitConfig {
+    pub fn policy_for(&self, instance: &str) -> &RateLimitPolicy {
+        self.instances.get(&queue_env_key(instance)).unwrap_or(&self.default)
+    }
+}
+
+// Waits until both the instance and the destination number (when known) have a token to spend, then
+// adds the human-like pause. The buckets live in Redis so every replica draws from the same budget.
+// Waits longer than `max_wait` fail as transient, so the delivery goes to the retry queues instead of
+// being held unacked.
+pub async fn throttle(ctx: &Context, instance: &str, number: Option<&str>) -> Result<(), ProcessError> {
+    let policy = ctx.env.rate_limits.policy_for(instance);
+    let mut buckets = Vec::new();
+    if let Some(bucket) = policy.instance {
+        buckets.push((instance_bucket_key(instance), bucket));
+    }
+    if let (Some(bucket), Some(number)) = (policy.number, number) {
+        buckets.push((number_bucket_key(instance, &recipient(&ctx.phone, number)), bucket));
+    }
+    let max_wait = Duration::from_millis(policy.max_wait.unwrap_or(DEFAULT_MAX_WAIT_MS));
+
+    if !buckets.is_empty() {
+        let mut redis_conn = ctx.redis.clone();
+        let mut waited = Duration::ZERO;
+        loop {
+            let wait = take_tokens(&mut redis_conn, &buckets).await?;
+            if wait.is_zero() {
+                break;
+            }
+            if waited + wait > max_wait {
+                return Err(ProcessError::Transient(format!(
+                    "Rate limit for instance {} needs another {:?} after waiting {:?}, retrying later",
+                    instance, wait, waited
+                )));
+            }
+            debug!("Rate limit reached for instance {}, waiting {:?}", instance, wait);
+            sleep(wait).await;
+            waited += wait;
+        }
+        if !waited.is_zero() {
+            info!("Send on instance {} was throttled for {:?}", instance, waited);
+        }
+    }
+
+    if let Some(delay) = &policy.delay {
+        let delay = rand::rng().random_range(delay.clone());
+        if delay > 0 {
+            sleep(Duration::from_millis(delay)).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rates_and_bursts() {
+        let policy = RateLimitPolicy::default()
+            .with_spec("instance=20/min,instance_burst=5,number=6/min,number_burst=2,delay=800-2500,max_wait=10000")
+            .unwrap();
+        assert_eq!(policy.instance, Some(TokenBucket { per_second: 20.0 / 60.0, burst: 5 }));
+        assert_eq!(policy.number, Some(TokenBucket { per_second: 0.1, burst: 2 }));
+        assert_eq!(policy.delay, Some(800..=2500));
+        assert_eq!(policy.max_wait, Some(10_000));
+    }
+
+    #[test]
+    fn keeps_unlisted_keys_from_the_base_policy() {
+        let base = RateLimitPolicy::default().with_spec("instance=1/s,instance_burst=3,number=2/h,delay=500").unwrap();
+        let policy = base.with_spec("instance=60/min,number=off").unwrap();
+        assert_eq!(policy.instance, Some(TokenBucket { per_second: 1.0, burst: 3 }));
+        assert_eq!(policy.number, None);
+        assert_eq!(policy.delay, Some(500..=500));
+    }
+
+    #[test]
+    fn defaults_burst_to_one() {
+        let policy = RateLimitPolicy::default().with_spec("number=10/s").unwrap();
+        assert_eq!(policy.number, Some(TokenBucket { per_second: 10.0, burst: 1 }));
+        assert_eq!(policy.instance, None);
+        assert_eq!(policy.max_wait, None);
+    }
+
+    #[test]
+    fn rejects_malformed_specs() {
+        let policy = RateLimitPolicy::default();
+        assert!(policy.with_spec("instance").is_err());
+        assert!(policy.with_spec("instance=20").is_err());
+        assert!(policy.with_spec("instance=20/day").is_err());
+        assert!(policy.with_spec("instance=0/s").is_err());
+        assert!(policy.with_spec("instance=-1/s").is_err());
+        assert!(policy.with_spec("instance=many/s").is_err());
+        assert!(policy.with_spec("instance_burst=5,instance=20/min").is_err());
+        assert!(policy.with_spec("instance=20/min,instance_burst=0").is_err());
+        assert!(policy.with_spec("delay=2500-800").is_err());
+        assert!(policy.with_spec("delay=fast").is_err());
+        assert!(policy.with_spec("max_wait=-1").is_err());
+        assert!(policy.with_spec("group=1/s").is_err());
+    }
+}
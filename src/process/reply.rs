@@ -0,0 +1,91 @@
+use std::time::Duration;
+use chrono::{SecondsFormat, Utc};
+use serde_json::{json, Value};
+use log::{error, info, warn};
+use crate::api::requests::HttpResponse;
+use crate::handlers::handler::Context;
+use crate::parser::library::Request;
+use crate::process::error::ProcessError;
+use crate::rabbit::publish::publish_json;
+use crate::redis_mod::results::store_request_result;
+
+pub const REQUEST_RESULT_EVENT: &str = "request.result";
+
+#[derive(Default)]
+pub struct ReplyTarget {
+    pub correlation_id: Option<String>,
+    pub message_id: Option<String>,
+    pub reply_to: Option<String>,
+    pub attempt: u32,
+}
+
+impl ReplyTarget {
+    // The id sent inside the request wins over the AMQP properties; a random one is the last resort.
+    pub fn correlation_id_for(&self, request: &Request) -> String {
+        request.correlation_id.clone()
+            .or_else(|| self.correlation_id.clone())
+            .or_else(|| self.message_id.clone())
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| hex::encode(rand::random::<[u8; 16]>()))
+    }
+}
+
+pub fn result_event(
+    request: &Request,
+    correlation_id: &str,
+    attempt: u32,
+    latency: Duration,
+    result: &Result<HttpResponse, ProcessError>,
+) -> Value {
+    let (response, error) = match result {
+        Ok(response) => (Some(response), response.error()),
+        Err(e) => (None, Some(e.clone())),
+    };
+    json!({
+        "event": REQUEST_RESULT_EVENT,
+        "correlation_id": correlation_id,
+        "action": request.action,
+        "method": request.method.to_ascii_uppercase(),
+        "url": request.url,
+        "attempt": attempt,
+        "success": error.is_none(),
+        "status": response.map(|r| r.status.as_u16()),
+        "headers": response.map(|r| Value::Object(r.headers.clone())),
+        "body": response.map(|r| r.body.clone()),
+        "latency_ms": latency.as_millis() as u64,
+        "error": error.map(|e| json!({
+            "kind": if e.is_transient() { "transient" } else { "permanent" },
+            "message": e.to_string(),
+            "retryable": e.is_transient(),
+        })),
+        "finished_at": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+    })
+}
+
+// Best effort: the request already had its side effect, so failing here must not trigger a resend.
+pub async fn publish_result(ctx: &Context, target: &ReplyTarget, correlation_id: &str, event: &Value) {
+    let results = &ctx.env.results;
+    if let Some(ttl) = results.ttl {
+        let mut redis_conn = ctx.redis.clone();
+        if let Err(e) = store_request_result(&mut redis_conn, correlation_id, event, ttl).await {
+            error!("Couldn't store result of request {} in Redis: {}", correlation_id, e);
+        }
+    }
+
+    let destination = match (&target.reply_to, &results.exchange) {
+        (Some(reply_to), _) if !reply_to.is_empty() => Some(("", reply_to.as_str())),
+        (_, Some(exchange)) => Some((exchange.as_str(), results.routing_key.as_str())),
+        _ => None,
+    };
+    let Some((exchange, routing_key)) = destination else {
+        return;
+    };
+    let Some(channel) = &ctx.channel else {
+        warn!("No AMQP channel available to publish the result of request {}", correlation_id);
+        return;
+    };
+    match publish_json(channel, exchange, routing_key, correlation_id, event).await {
+        Ok(_) => info!("Published result of request {} to {}", correlation_id, if exchange.is_empty() { routing_key } else { exchange }),
+        Err(e) => error!("Couldn't publish result of request {}: {}", correlation_id, e),
+    }
+}
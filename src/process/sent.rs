@@ -0,0 +1,57 @@
+use chrono::{DateTime, SecondsFormat};
+use log::warn;
+use redis::AsyncCommands;
+use crate::handlers::handler::Context;
+use crate::parser::content::{normalize, parse_message};
+use crate::parser::library::{IncomingMessage, SendMessageResponse};
+use crate::process::error::ProcessError;
+use crate::process::persist::{normalize_addresses, persist_message};
+use crate::process::idempotency::{process_once, whatsapp_message_id, WHATSAPP_SCOPE};
+use crate::redis_mod::redis::chat_key;
+
+// Older producers publish the response without the instance name; the chat already knows which
+// instance it belongs to, so the message is still stored instead of being dead-lettered.
+async fn instance_of_chat(ctx: &Context, remote_jid: &str) -> Result<String, ProcessError> {
+    let chat_id = ctx.phone.normalize_jid(remote_jid);
+    let mut redis_conn = ctx.redis.clone();
+    let instance_id: Option<String> = redis_conn.hget(chat_key(&chat_id), "instance_id").await?;
+    let instance_id = instance_id.unwrap_or_default();
+    if instance_id.is_empty() {
+        warn!("SendMessageResponse for chat {} has no instance name and the chat has none either, storing it without one", chat_id);
+    } else {
+        warn!("SendMessageResponse for chat {} has no instance name, using the chat's instance {}", chat_id, instance_id);
+    }
+    Ok(instance_id)
+}
+
+pub async fn process_sent_message(data: &[u8], ctx: &Context, redelivered: bool) -> Result<(), ProcessError> {
+    let response = serde_json::from_slice::<SendMessageResponse>(data)
+        .map_err(|e| ProcessError::Permanent(format!("Failed to deserialize SendMessageResponse: {}", e)))?;
+    if let Some(status_string) = response.status_string
+        && let Some(key) = status_string.key
+        && status_string.message.is_some() {
+        let instance_id = match response.instance.filter(|instance| !instance.is_empty()) {
+            Some(instance_id) => instance_id,
+            None => instance_of_chat(ctx, &key.remote_jid).await?,
+        };
+        let message = parse_message(status_string.message.as_ref());
+        let content = normalize(&message, None, status_string.message_type.as_deref().unwrap_or(""));
+        let timestamp = status_string.message_timestamp
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+            .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true))
+            .unwrap_or_default();
+        let mut record = IncomingMessage {
+            instance_id,
+            message_id: key.id.clone(),
+            from_me: true,
+            message: content.into_message(format!("msg_{}", key.id), String::new(), key.remote_jid.clone(), timestamp),
+            remote_jid: key.remote_jid,
+            media_base64: None,
+            media_fetch: None,
+        };
+        normalize_addresses(&mut record, &ctx.phone);
+        let message_id = whatsapp_message_id(&record.instance_id, &record.message_id);
+        process_once(ctx, WHATSAPP_SCOPE, &message_id, redelivered, || persist_message(&record, ctx)).await?;
+    }
+    Ok(())
+}
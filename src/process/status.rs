@@ -0,0 +1,35 @@
+use serde_json::Value;
+use log::{info, warn};
+use crate::database::insert::record_wa_message_status;
+use crate::handlers::handler::Context;
+use crate::parser::provider::Provider;
+use crate::process::error::ProcessError;
+use crate::events::publish::publish_status;
+use crate::redis_mod::redis::update_message_status;
+
+pub async fn process_status(data: &[u8], ctx: &Context, provider: Option<Provider>) -> Result<(), ProcessError> {
+    let value: Value = serde_json::from_slice(data)?;
+    let provider = provider
+        .or_else(|| Provider::detect_status(&value))
+        .ok_or_else(|| ProcessError::Permanent("Couldn't detect the webhook provider for status event".to_string()))?;
+
+    let updates = provider.parse_status(&value)?;
+    if updates.is_empty() {
+        warn!("Skipping {:?} status event without a known message status", provider);
+        return Ok(());
+    }
+
+    let client = ctx.db.get().await?;
+    let mut redis_conn = ctx.redis.clone();
+    for update in &updates {
+        let updated = record_wa_message_status(&client, update).await?;
+        let message_id = format!("msg_{}", update.message_id);
+        let cached = update_message_status(&mut redis_conn, &message_id, update).await?;
+        publish_status(&mut redis_conn, &ctx.phone.normalize_jid(&update.remote_jid), &message_id, update).await;
+        info!(
+            "Message {} in {} is now {} (db rows: {}, cached: {})",
+            update.message_id, update.remote_jid, update.status.as_str(), updated, cached
+        );
+    }
+    Ok(())
+}
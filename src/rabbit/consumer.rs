@@ -0,0 +1,124 @@
+use std::sync::Arc;
+use futures::StreamExt;
+use lapin::options::BasicCancelOptions;
+use log::{error, info, warn};
+use tokio::select;
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, Duration};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+use crate::config::config::QueueConfig;
+use crate::handlers::handler::{Context, MessageHandler};
+use crate::rabbit;
+
+pub async fn run_consumer(
+    rabbit_url: &str,
+    queue: &QueueConfig,
+    handler: Arc<dyn MessageHandler>,
+    mut ctx: Context,
+    shutdown_timeout: Duration,
+    shutdown: &CancellationToken,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let queue_name = queue.name.as_str();
+    let options = &queue.options;
+    let setup = select! {
+        setup = rabbit::setup_rabbit::create_rabbitmq_consumer(rabbit_url, queue_name, options) => setup,
+        _ = shutdown.cancelled() => return Ok(()),
+    };
+    let (mut consumer, channel, connection) = match setup {
+        Some((consumer, channel, connection)) => (consumer, channel, connection),
+        None => return Err("Failed to create RabbitMQ consumer".into()),
+    };
+
+    for target in ctx.env.ingestion.route_queues() {
+        if let Err(e) = rabbit::setup_rabbit::declare_queue(&channel, &target).await {
+            error!("Failed to declare route queue {}: {}", target, e);
+            return Err(Box::new(e));
+        }
+    }
+
+    if let Some(exchange) = &ctx.env.results.exchange
+        && let Err(e) = rabbit::publish::declare_topic_exchange(&channel, exchange).await {
+        error!("Failed to declare result exchange {}: {}", exchange, e);
+        return Err(Box::new(e));
+    }
+    ctx.channel = Some(channel.clone());
+
+    let workers = Arc::new(Semaphore::new(options.concurrency));
+    let in_flight = TaskTracker::new();
+    let abort = CancellationToken::new();
+
+    info!("Consumer ready for {} with handler {} (prefetch {}, concurrency {}), waiting for webhooks...", queue_name, queue.handler, options.prefetch, options.concurrency);
+    info!("Press Ctrl+C to exit");
+
+    loop {
+        let permit = select! {
+            permit = Arc::clone(&workers).acquire_owned() => match permit {
+                Ok(permit) => permit,
+                Err(_) => break,
+            },
+            _ = shutdown.cancelled() => break,
+        };
+
+        select! {
+            delivery_result = consumer.next() => {
+                match delivery_result {
+                    Some(Ok(delivery)) => {
+                        let queue_name = queue_name.to_string();
+                        let handler = Arc::clone(&handler);
+                        let ctx = ctx.clone();
+                        let channel = channel.clone();
+                        let retry = options.retry.clone();
+                        let abort = abort.clone();
+                        in_flight.spawn(async move {
+                            select! {
+                                outcome = handler.handle(&delivery, &ctx) => {
+                                    rabbit::delivery::settle(&delivery, &channel, &queue_name, &retry, outcome).await;
+                                }
+                                _ = abort.cancelled() => {
+                                    rabbit::delivery::requeue(&delivery, &queue_name).await;
+                                }
+                            }
+                            drop(permit);
+                        });
+                    },
+                    Some(Err(e)) => {
+                        error!("Error receiving message: {}", e);
+                        return Err(Box::new(e));
+                    },
+                    None => {
+                        warn!("Consumer channel closed");
+                        return Err("Consumer channel closed unexpectedly".into());
+                    }
+                }
+            },
+
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    info!("Stopping consumer for {}", queue_name);
+    if let Err(e) = channel.basic_cancel(consumer.tag().as_str(), BasicCancelOptions::default()).await {
+        error!("Failed to cancel consumer for {}: {}", queue_name, e);
+    }
+
+    in_flight.close();
+    if !in_flight.is_empty() {
+        info!("Waiting up to {:?} for {} in-flight messages on {}", shutdown_timeout, in_flight.len(), queue_name);
+    }
+    if timeout(shutdown_timeout, in_flight.wait()).await.is_err() {
+        warn!("Shutdown deadline reached with {} messages still in flight on {}, requeueing them", in_flight.len(), queue_name);
+        abort.cancel();
+        in_flight.wait().await;
+    }
+
+    if let Err(e) = channel.close(200, "Consumer shutdown").await {
+        error!("Failed to close channel for {}: {}", queue_name, e);
+    }
+    if let Err(e) = connection.close(200, "Consumer shutdown").await {
+        error!("Failed to close connection for {}: {}", queue_name, e);
+    }
+    info!("Consumer for {} stopped", queue_name);
+
+    Ok(())
+}
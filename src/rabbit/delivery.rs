@@ -0,0 +1,120 @@
+use lapin::message::Delivery;
+use lapin::options::{BasicAckOptions, BasicNackOptions, BasicPublishOptions};
+use lapin::types::{AMQPValue, FieldTable, LongString};
+use lapin::Channel;
+use log::{error, info, warn};
+use crate::handlers::handler::Outcome;
+use crate::rabbit::retry::{attempts_made, schedule_retry, RetryPolicy, DELIVERY_COUNT_HEADER, LAST_ERROR_HEADER, RETRY_COUNT_HEADER};
+use crate::rabbit::setup_rabbit::{dead_letter_queue, DEAD_LETTER_EXCHANGE};
+
+pub const ROUTED_FROM_HEADER: &str = "x-routed-from";
+
+async fn ack(delivery: &Delivery) {
+    if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+        error!("Failed to acknowledge message: {}", e);
+    }
+}
+
+async fn nack(delivery: &Delivery, requeue: bool) {
+    let options = BasicNackOptions {
+        multiple: false,
+        requeue,
+    };
+    if let Err(e) = delivery.nack(options).await {
+        error!("Failed to reject message: {}", e);
+    }
+}
+
+pub async fn requeue(delivery: &Delivery, queue_name: &str) {
+    warn!("Requeueing unfinished message from {}", queue_name);
+    nack(delivery, true).await;
+}
+
+async fn publish_dead_letter(channel: &Channel, delivery: &Delivery, queue_name: &str, error: &str) -> Result<(), lapin::Error> {
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+    headers.insert(LAST_ERROR_HEADER.into(), AMQPValue::LongString(LongString::from(error)));
+    let properties = delivery.properties.clone().with_headers(headers);
+    channel
+        .basic_publish(DEAD_LETTER_EXCHANGE, queue_name, BasicPublishOptions::default(), &delivery.data, properties)
+        .await?
+        .await?;
+    Ok(())
+}
+
+// Published to the DLX and then acked, so existing queues don't need x-dead-letter-* arguments.
+async fn dead_letter(channel: &Channel, delivery: &Delivery, queue_name: &str, error: &str) {
+    warn!("Dead-lettering message from {} to {}", queue_name, dead_letter_queue(queue_name));
+    match publish_dead_letter(channel, delivery, queue_name, error).await {
+        Ok(_) => ack(delivery).await,
+        Err(e) => {
+            error!("Failed to dead-letter message from {}, requeueing it: {}", queue_name, e);
+            nack(delivery, true).await;
+        }
+    }
+}
+
+async fn forward(channel: &Channel, delivery: &Delivery, queue_name: &str, target: &str) -> Result<(), lapin::Error> {
+    // The target queue starts its own retry count.
+    let mut headers = FieldTable::default();
+    if let Some(existing) = delivery.properties.headers() {
+        for (key, value) in existing.inner() {
+            if key.as_str() != DELIVERY_COUNT_HEADER && key.as_str() != RETRY_COUNT_HEADER {
+                headers.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    headers.insert(ROUTED_FROM_HEADER.into(), AMQPValue::LongString(LongString::from(queue_name)));
+    let properties = delivery.properties.clone().with_headers(headers);
+    channel
+        .basic_publish("", target, BasicPublishOptions::default(), &delivery.data, properties)
+        .await?
+        .await?;
+    Ok(())
+}
+
+pub async fn settle(
+    delivery: &Delivery,
+    channel: &Channel,
+    queue_name: &str,
+    policy: &RetryPolicy,
+    outcome: Outcome,
+) {
+    match outcome {
+        Outcome::Ack => {
+            info!("Successfully processed message from {}", queue_name);
+            ack(delivery).await;
+        }
+        Outcome::Retry(e) => {
+            let attempt = attempts_made(delivery) + 1;
+            if attempt >= policy.max_attempts {
+                error!("Giving up on message from {} after {} attempts: {}", queue_name, attempt, e);
+                dead_letter(channel, delivery, queue_name, &e).await;
+                return;
+            }
+
+            let delay = policy.delay_for(attempt);
+            warn!("Attempt {}/{} for message from {} failed, retrying in {:?}: {}", attempt, policy.max_attempts, queue_name, delay, e);
+            match schedule_retry(channel, delivery, queue_name, attempt, delay, &e).await {
+                Ok(_) => ack(delivery).await,
+                Err(publish_error) => {
+                    error!("Failed to schedule retry, requeueing message: {}", publish_error);
+                    nack(delivery, true).await;
+                }
+            }
+        }
+        Outcome::Route(target) => match forward(channel, delivery, queue_name, &target).await {
+            Ok(_) => {
+                info!("Routed message from {} to {}", queue_name, target);
+                ack(delivery).await;
+            }
+            Err(e) => {
+                error!("Failed to route message from {} to {}, requeueing it: {}", queue_name, target, e);
+                nack(delivery, true).await;
+            }
+        },
+        Outcome::Reject(e) => {
+            error!("Error processing message from {}: {}", queue_name, e);
+            dead_letter(channel, delivery, queue_name, &e).await;
+        }
+    }
+}
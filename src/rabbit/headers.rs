@@ -0,0 +1,34 @@
+use lapin::message::Delivery;
+use lapin::types::{AMQPValue, FieldTable};
+
+fn find<'a>(headers: &'a FieldTable, name: &str) -> Option<&'a AMQPValue> {
+    headers
+        .inner()
+        .iter()
+        .find(|(key, _)| key.as_str() == name)
+        .map(|(_, value)| value)
+}
+
+pub fn header_u32(headers: &FieldTable, name: &str) -> u32 {
+    find(headers, name)
+        .and_then(|value| match value {
+            AMQPValue::ShortShortUInt(v) => Some(*v as u32),
+            AMQPValue::ShortUInt(v) => Some(*v as u32),
+            AMQPValue::LongUInt(v) => Some(*v),
+            AMQPValue::ShortShortInt(v) => u32::try_from(*v).ok(),
+            AMQPValue::ShortInt(v) => u32::try_from(*v).ok(),
+            AMQPValue::LongInt(v) => u32::try_from(*v).ok(),
+            AMQPValue::LongLongInt(v) => u32::try_from(*v).ok(),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+pub fn header_str(delivery: &Delivery, name: &str) -> Option<String> {
+    let headers = delivery.properties.headers().as_ref()?;
+    match find(headers, name)? {
+        AMQPValue::LongString(v) => Some(String::from_utf8_lossy(v.as_bytes()).to_string()),
+        AMQPValue::ShortString(v) => Some(v.as_str().to_string()),
+        _ => None,
+    }
+}
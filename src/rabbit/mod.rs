@@ -1 +1,6 @@
-pub mod setup_rabbit;
\ No newline at end of file
+pub mod setup_rabbit;
+pub mod delivery;
+pub mod retry;
+pub mod consumer;
+pub mod headers;
+pub mod publish;
\ No newline at end of file
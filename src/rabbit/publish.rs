@@ -0,0 +1,30 @@
+use lapin::options::{BasicPublishOptions, ExchangeDeclareOptions};
+use lapin::types::{FieldTable, ShortString};
+use lapin::{BasicProperties, Channel, ExchangeKind};
+use serde_json::Value;
+
+pub async fn declare_topic_exchange(channel: &Channel, exchange: &str) -> Result<(), lapin::Error> {
+    let options = ExchangeDeclareOptions {
+        durable: true,
+        ..ExchangeDeclareOptions::default()
+    };
+    channel.exchange_declare(exchange, ExchangeKind::Topic, options, FieldTable::default()).await
+}
+
+pub async fn publish_json(
+    channel: &Channel,
+    exchange: &str,
+    routing_key: &str,
+    correlation_id: &str,
+    payload: &Value,
+) -> Result<(), lapin::Error> {
+    let properties = BasicProperties::default()
+        .with_content_type(ShortString::from("application/json"))
+        .with_correlation_id(ShortString::from(correlation_id))
+        .with_delivery_mode(2);
+    channel
+        .basic_publish(exchange, routing_key, BasicPublishOptions::default(), payload.to_string().as_bytes(), properties)
+        .await?
+        .await?;
+    Ok(())
+}
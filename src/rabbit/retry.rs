@@ -0,0 +1,90 @@
+use std::time::Duration;
+use lapin::{
+    message::Delivery,
+    options::{BasicPublishOptions, QueueDeclareOptions},
+    types::{AMQPValue, FieldTable, LongString, ShortString},
+    Channel,
+};
+use rand::Rng;
+use crate::rabbit::headers::header_u32;
+
+pub const RETRY_COUNT_HEADER: &str = "x-retry-count";
+pub const DELIVERY_COUNT_HEADER: &str = "x-delivery-count";
+pub const LAST_ERROR_HEADER: &str = "x-last-error";
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: Duration,
+}
+
+impl RetryPolicy {
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        let jitter_ms = self.jitter.as_millis() as u64;
+        if jitter_ms == 0 {
+            return delay;
+        }
+        delay + Duration::from_millis(rand::rng().random_range(0..=jitter_ms))
+    }
+}
+
+pub fn retry_queue(queue_name: &str, attempt: u32) -> String {
+    format!("{}.retry.{}", queue_name, attempt)
+}
+
+pub async fn setup_retry_queues(channel: &Channel, queue_name: &str, policy: &RetryPolicy) -> Result<(), lapin::Error> {
+    let options = QueueDeclareOptions {
+        durable: true,
+        ..QueueDeclareOptions::default()
+    };
+    for attempt in 1..policy.max_attempts {
+        let mut args = FieldTable::default();
+        args.insert("x-dead-letter-exchange".into(), AMQPValue::LongString(LongString::from("")));
+        args.insert("x-dead-letter-routing-key".into(), AMQPValue::LongString(LongString::from(queue_name)));
+        channel.queue_declare(&retry_queue(queue_name, attempt), options, args).await?;
+    }
+    Ok(())
+}
+
+pub fn attempts_made(delivery: &Delivery) -> u32 {
+    match delivery.properties.headers() {
+        Some(headers) => header_u32(headers, RETRY_COUNT_HEADER) + header_u32(headers, DELIVERY_COUNT_HEADER),
+        None => 0,
+    }
+}
+
+pub async fn schedule_retry(
+    channel: &Channel,
+    delivery: &Delivery,
+    queue_name: &str,
+    attempt: u32,
+    delay: Duration,
+    error: &str,
+) -> Result<(), lapin::Error> {
+    let mut headers = FieldTable::default();
+    if let Some(existing) = delivery.properties.headers() {
+        for (key, value) in existing.inner() {
+            if key.as_str() != DELIVERY_COUNT_HEADER {
+                headers.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    headers.insert(RETRY_COUNT_HEADER.into(), AMQPValue::LongUInt(attempt));
+    headers.insert(LAST_ERROR_HEADER.into(), AMQPValue::LongString(LongString::from(error)));
+
+    let properties = delivery
+        .properties
+        .clone()
+        .with_headers(headers)
+        .with_expiration(ShortString::from(delay.as_millis().to_string()));
+
+    channel
+        .basic_publish("", &retry_queue(queue_name, attempt), BasicPublishOptions::default(), &delivery.data, properties)
+        .await?
+        .await?;
+    Ok(())
+}
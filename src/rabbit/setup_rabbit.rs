@@ -1,10 +1,12 @@
 use lapin::{
-    options::{BasicConsumeOptions, BasicQosOptions, QueueDeclareOptions},
+    options::{BasicConsumeOptions, BasicQosOptions, ConfirmSelectOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions},
     types::{FieldTable, LongString, AMQPValue},
-    ConnectionProperties, Consumer, Connection
+    Channel, ConnectionProperties, Consumer, Connection, ExchangeKind
 };
 use log::{info, error};
 use tokio::time::{sleep, Duration};
+use crate::config::config::ConsumerOptions;
+use crate::rabbit::retry::setup_retry_queues;
 
 pub async fn connect_rabbitmq(rabbit_url: &str, queue_name: &str) -> Result<Connection, lapin::Error> {
     let options = ConnectionProperties::default()
@@ -12,21 +14,57 @@ pub async fn connect_rabbitmq(rabbit_url: &str, queue_name: &str) -> Result<Conn
     Connection::connect(rabbit_url, options).await
 }
 
-pub async fn setup_consumer(connection: &Connection, queue_name: &str) -> Result<Consumer, lapin::Error> {
-    let channel = connection.create_channel().await?;
-    
-    channel.basic_qos(1, BasicQosOptions::default()).await?;
-    
-    let queue_options = QueueDeclareOptions {
+pub const DEAD_LETTER_EXCHANGE: &str = "wasol.dlx";
+
+pub fn dead_letter_queue(queue_name: &str) -> String {
+    format!("{}.dlq", queue_name)
+}
+
+fn durable_queue_options() -> QueueDeclareOptions {
+    QueueDeclareOptions {
         passive: false,
         durable: true,
         ..QueueDeclareOptions::default()
-    };
-    
+    }
+}
+
+fn quorum_args() -> FieldTable {
     let mut args = FieldTable::default();
     args.insert("x-queue-type".into(), AMQPValue::LongString(LongString::from("quorum")));
+    args
+}
+
+async fn setup_dead_letter(channel: &Channel, queue_name: &str) -> Result<(), lapin::Error> {
+    let exchange_options = ExchangeDeclareOptions {
+        durable: true,
+        ..ExchangeDeclareOptions::default()
+    };
+    channel.exchange_declare(DEAD_LETTER_EXCHANGE, ExchangeKind::Direct, exchange_options, FieldTable::default()).await?;
+
+    let dlq = dead_letter_queue(queue_name);
+    channel.queue_declare(&dlq, durable_queue_options(), quorum_args()).await?;
+    channel.queue_bind(&dlq, DEAD_LETTER_EXCHANGE, queue_name, QueueBindOptions::default(), FieldTable::default()).await?;
+    info!("Dead-letter queue {} bound to {}", dlq, DEAD_LETTER_EXCHANGE);
+    Ok(())
+}
+
+// Same arguments as before dead-lettering existed: redeclaring a live queue with different arguments is
+// a PRECONDITION_FAILED, so dead letters are published explicitly instead of relying on queue arguments.
+pub async fn declare_queue(channel: &Channel, queue_name: &str) -> Result<(), lapin::Error> {
+    channel.queue_declare(queue_name, durable_queue_options(), quorum_args()).await?;
+    Ok(())
+}
+
+pub async fn setup_consumer(connection: &Connection, queue_name: &str, options: &ConsumerOptions) -> Result<(Channel, Consumer), lapin::Error> {
+    let channel = connection.create_channel().await?;
+    
+    channel.basic_qos(options.prefetch, BasicQosOptions::default()).await?;
+    channel.confirm_select(ConfirmSelectOptions::default()).await?;
+
+    setup_dead_letter(&channel, queue_name).await?;
+    setup_retry_queues(&channel, queue_name, &options.retry).await?;
     
-    channel.queue_declare(queue_name, queue_options, args).await?;
+    declare_queue(&channel, queue_name).await?;
     
     let consumer = channel
         .basic_consume(
@@ -37,18 +75,18 @@ pub async fn setup_consumer(connection: &Connection, queue_name: &str) -> Result
         )
         .await?;
     
-    Ok(consumer)
+    Ok((channel, consumer))
 }
 
-pub async fn create_rabbitmq_consumer(rabbit_url: &str, queue_name: &str) -> Option<(Consumer, Connection)> {
+pub async fn create_rabbitmq_consumer(rabbit_url: &str, queue_name: &str, options: &ConsumerOptions) -> Option<(Consumer, Channel, Connection)> {
     loop {
         match connect_rabbitmq(rabbit_url, queue_name).await {
             Ok(connection) => {
                 info!("RabbitMQ connection established");
-                match setup_consumer(&connection, queue_name).await {
-                    Ok(consumer) => {
+                match setup_consumer(&connection, queue_name, options).await {
+                    Ok((channel, consumer)) => {
                         info!("Consumer set up successfully");
-                        return Some((consumer, connection));
+                        return Some((consumer, channel, connection));
                     }
                     Err(e) => {
                         error!("Failed to set up consumer: {}", e);
@@ -0,0 +1,49 @@
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use std::time::Duration;
+
+pub fn processed_key(scope: &str, id: &str) -> String {
+    format!("processed:{}:{}", scope, id)
+}
+
+pub const PROCESSING: &str = "processing";
+pub const DONE: &str = "done";
+
+pub async fn claim(redis_conn: &mut ConnectionManager, key: &str, ttl: Duration) -> redis::RedisResult<bool> {
+    let options = SetOptions::default()
+        .conditional_set(ExistenceCheck::NX)
+        .with_expiration(SetExpiry::EX(ttl.as_secs().max(1)));
+    let set: Option<String> = redis_conn.set_options(key, PROCESSING, options).await?;
+    Ok(set.is_some())
+}
+
+pub async fn claim_state(redis_conn: &mut ConnectionManager, key: &str) -> redis::RedisResult<Option<String>> {
+    redis_conn.get(key).await
+}
+
+pub async fn take_over(redis_conn: &mut ConnectionManager, key: &str, ttl: Duration) -> redis::RedisResult<()> {
+    let _: () = redis_conn.set_ex(key, PROCESSING, ttl.as_secs().max(1)).await?;
+    Ok(())
+}
+
+pub async fn mark_done(redis_conn: &mut ConnectionManager, key: &str, ttl: Duration) -> redis::RedisResult<()> {
+    let _: () = redis_conn.set_ex(key, DONE, ttl.as_secs().max(1)).await?;
+    Ok(())
+}
+
+pub async fn release(redis_conn: &mut ConnectionManager, key: &str) -> redis::RedisResult<()> {
+    let _: i64 = redis_conn.del(key).await?;
+    Ok(())
+}
+
+pub async fn count_duplicate(redis_conn: &mut ConnectionManager, scope: &str) -> redis::RedisResult<i64> {
+    redis_conn.incr(format!("metrics:duplicates:{}", scope), 1).await
+}
+
+pub fn ingestion_metrics_key(instance_id: &str) -> String {
+    format!("metrics:ingestion:{}", if instance_id.is_empty() { "unknown" } else { instance_id })
+}
+
+pub async fn count_ingestion(redis_conn: &mut ConnectionManager, instance_id: &str, category: &str, action: &str) -> redis::RedisResult<i64> {
+    redis_conn.hincr(ingestion_metrics_key(instance_id), format!("{}:{}", category, action), 1).await
+}
@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, HashMap};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, ErrorKind, RedisError};
+use log::warn;
+use crate::phone::jid::Jid;
+use crate::phone::normalize::PhoneNormalizer;
+use crate::redis_mod::migrate::list_keys;
+use crate::redis_mod::redis::{chat_key, chat_messages_key, instance_chats_key, message_key};
+
+// Attempts per group before giving up when consumers keep writing to the chats being merged.
+const MAX_MERGE_ATTEMPTS: usize = 5;
+
+#[derive(Default, Debug)]
+pub struct ChatMergeReport {
+    pub groups: usize,
+    pub chats: usize,
+    pub messages: usize,
+}
+
+#[derive(Debug)]
+pub struct ChatMerge {
+    pub target: String,
+    pub sources: Vec<String>,
+    pub messages: usize,
+    pub fields: Vec<(String, String)>,
+}
+
+struct ChatSnapshot {
+    id: String,
+    fields: HashMap<String, String>,
+    messages: Vec<(String, i64)>,
+}
+
+impl ChatSnapshot {
+    fn last_message_at(&self) -> i64 {
+        self.fields.get("last_message_at").and_then(|v| v.parse().ok()).unwrap_or(0)
+    }
+
+    fn instance_id(&self) -> Option<&str> {
+        self.fields.get("instance_id").map(String::as_str).filter(|i| !i.is_empty())
+    }
+}
+
+async fn load_chat(redis_conn: &mut ConnectionManager, chat_id: &str) -> redis::RedisResult<ChatSnapshot> {
+    let (fields, messages): (HashMap<String, String>, Vec<(String, i64)>) = redis::pipe()
+        .hgetall(chat_key(chat_id))
+        .zrange_withscores(chat_messages_key(chat_id), 0, -1)
+        .query_async(redis_conn)
+        .await?;
+    Ok(ChatSnapshot { id: chat_id.to_string(), fields, messages })
+}
+
+// Chats are folded from the oldest to the most recent activity, so situation and tabulation end up as
+// the values of the conversation that was touched last; empty values never erase them. The agent is
+// always the one of the most recent chat, so a chat that was unassigned last stays unassigned.
+fn reconcile(target: &str, chats: &[ChatSnapshot]) -> Vec<(String, String)> {
+    let mut merged: BTreeMap<String, String> = BTreeMap::new();
+    for chat in chats {
+        for (field, value) in &chat.fields {
+            if !value.is_empty() || !merged.contains_key(field) {
+                merged.insert(field.clone(), value.clone());
+            }
+        }
+    }
+    let agent_id = chats.last().and_then(|chat| chat.fields.get("agent_id")).cloned().unwrap_or_default();
+    merged.insert("agent_id".to_string(), agent_id);
+
+    let unread: i64 = chats.iter()
+        .filter_map(|chat| chat.fields.get("unread_count").and_then(|v| v.parse::<i64>().ok()))
+        .sum();
+    let last_message_at = chats.iter().map(ChatSnapshot::last_message_at).max().unwrap_or(0);
+    let jid = Jid::parse(target);
+    merged.insert("id".to_string(), target.to_string());
+    merged.insert("kind".to_string(), jid.kind.as_str().to_string());
+    merged.insert("unread_count".to_string(), unread.to_string());
+    merged.insert("last_message_at".to_string(), last_message_at.to_string());
+    if jid.is_user() {
+        merged.insert("number".to_string(), jid.user);
+    }
+    merged.into_iter().collect()
+}
+
+// Merges one group inside a WATCH on every chat involved; returns None when a consumer wrote to one
+// of them before EXEC, so the caller can reload and try again. The connection belongs to the CLI alone,
+// so no other command runs between WATCH and EXEC.
+async fn merge_group(
+    redis_conn: &mut ConnectionManager,
+    target: &str,
+    members: &[String],
+    dry_run: bool,
+) -> redis::RedisResult<Option<ChatMerge>> {
+    let mut watched: Vec<String> = Vec::new();
+    for chat_id in members.iter().map(String::as_str).chain(std::iter::once(target)) {
+        watched.push(chat_key(chat_id));
+        watched.push(chat_messages_key(chat_id));
+    }
+    let _: () = redis::cmd("WATCH").arg(&watched).query_async(redis_conn).await?;
+
+    let mut chats = Vec::new();
+    if !members.iter().any(|member| member == target) {
+        chats.push(load_chat(redis_conn, target).await?);
+    }
+    for member in members {
+        chats.push(load_chat(redis_conn, member).await?);
+    }
+    chats.sort_by_key(ChatSnapshot::last_message_at);
+
+    let sources: Vec<String> = members.iter().filter(|member| *member != target).cloned().collect();
+    let fields = reconcile(target, &chats);
+    let moved: Vec<&(String, i64)> = chats.iter()
+        .filter(|chat| chat.id != target)
+        .flat_map(|chat| chat.messages.iter())
+        .collect();
+    let merge = ChatMerge { target: target.to_string(), sources, messages: moved.len(), fields };
+    if dry_run {
+        let _: () = redis::cmd("UNWATCH").query_async(redis_conn).await?;
+        return Ok(Some(merge));
+    }
+
+    let target_key = chat_key(target);
+    let target_messages = chat_messages_key(target);
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    pipe.hset_multiple(&target_key, &merge.fields).ignore();
+    for (message_id, score) in &moved {
+        pipe.zadd(&target_messages, message_id, *score).ignore();
+        pipe.hset(message_key(message_id), "chat_id", target).ignore();
+    }
+    for chat in chats.iter().filter(|chat| chat.id != target) {
+        if let Some(instance_id) = chat.instance_id() {
+            pipe.zrem(instance_chats_key(instance_id), &chat.id).ignore();
+        }
+        pipe.srem("chats", &chat.id).ignore();
+        pipe.del(chat_key(&chat.id)).ignore();
+        pipe.del(chat_messages_key(&chat.id)).ignore();
+    }
+    let last_message_at = chats.iter().map(ChatSnapshot::last_message_at).max().unwrap_or(0);
+    if let Some(instance_id) = chats.iter().rev().find_map(ChatSnapshot::instance_id) {
+        pipe.cmd("ZADD").arg(instance_chats_key(instance_id)).arg("GT").arg(last_message_at).arg(target).ignore();
+    }
+    pipe.sadd("chats", target).ignore();
+    let committed: Option<()> = pipe.query_async(redis_conn).await?;
+    Ok(committed.map(|_| merge))
+}
+
+pub async fn merge_duplicate_chats(
+    redis_conn: &mut ConnectionManager,
+    phone: &PhoneNormalizer,
+    dry_run: bool,
+) -> redis::RedisResult<(ChatMergeReport, Vec<ChatMerge>)> {
+    let lists = list_keys(redis_conn).await?;
+    if !lists.is_empty() {
+        return Err(RedisError::from((
+            ErrorKind::ClientError,
+            "Chats are still stored as lists",
+            format!("{} list key(s) found, run `migrate redis` before merging", lists.len()),
+        )));
+    }
+
+    let chat_ids: Vec<String> = redis_conn.smembers("chats").await?;
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for chat_id in chat_ids {
+        groups.entry(phone.chat_id_for_number(&chat_id)).or_default().push(chat_id);
+    }
+
+    let mut report = ChatMergeReport::default();
+    let mut merges = Vec::new();
+    for (target, members) in groups {
+        if members.iter().all(|member| *member == target) {
+            continue;
+        }
+        let mut attempt = 1;
+        let result = loop {
+            match merge_group(redis_conn, &target, &members, dry_run).await {
+                Ok(None) if attempt < MAX_MERGE_ATTEMPTS => {
+                    warn!("Chats {:?} changed while merging into {}, retrying", members, target);
+                    attempt += 1;
+                }
+                Ok(None) => break Err(RedisError::from((
+                    ErrorKind::ClientError,
+                    "Chats kept changing while merging",
+                    format!("gave up on {} after {} attempts, stop the consumers and run again", target, attempt),
+                ))),
+                Ok(Some(merge)) => break Ok(merge),
+                Err(e) => break Err(e),
+            }
+        };
+        match result {
+            Ok(merge) => {
+                report.groups += 1;
+                report.chats += merge.sources.len();
+                report.messages += merge.messages;
+                merges.push(merge);
+            }
+            Err(e) => {
+                warn!("Couldn't merge chats {:?} into {}: {}", members, target, e);
+                return Err(e);
+            }
+        }
+    }
+    Ok((report, merges))
+}
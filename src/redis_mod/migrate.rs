@@ -0,0 +1,125 @@
+use chrono::Utc;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde_json::Value;
+use log::{info, warn};
+use crate::parser::content::parse_timestamp;
+use crate::redis_mod::redis::{chat_messages_key, instance_chats_key, message_key, message_ref, to_fields};
+
+#[derive(Default, Debug)]
+pub struct RedisMigrationReport {
+    pub chats: usize,
+    pub messages: usize,
+    pub skipped: usize,
+}
+
+pub async fn list_keys(redis_conn: &mut ConnectionManager) -> redis::RedisResult<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut iter = redis_conn.scan_match::<_, String>("chat:*").await?;
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    drop(iter);
+
+    let mut lists = Vec::new();
+    for key in keys {
+        let key_type: String = redis::cmd("TYPE").arg(&key).query_async(redis_conn).await?;
+        if key_type == "list" {
+            lists.push(key);
+        }
+    }
+    // Chat metadata has to become a hash before message keys update last_message_at on it.
+    lists.sort_by_key(|key| key.ends_with(":messages"));
+    Ok(lists)
+}
+
+async fn migrate_chat(redis_conn: &mut ConnectionManager, key: &str, report: &mut RedisMigrationReport) -> redis::RedisResult<()> {
+    let chat_id = key.trim_start_matches("chat:");
+    let first: Option<String> = redis_conn.lindex(key, 0).await?;
+    let metadata = match first.as_deref().map(serde_json::from_str::<Value>) {
+        Some(Ok(metadata)) if metadata.is_object() => metadata,
+        _ => {
+            warn!("Skipping {}: first element isn't a JSON object", key);
+            report.skipped += 1;
+            return Ok(());
+        }
+    };
+
+    let mut fields = to_fields(&metadata);
+    fields.retain(|(field, _)| field != "id");
+    fields.push(("id".to_string(), chat_id.to_string()));
+    let instance_id = metadata.get("instance_id").and_then(|v| v.as_str()).unwrap_or("");
+
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    pipe.del(key).ignore();
+    pipe.hset_multiple(key, &fields).ignore();
+    pipe.hset_nx(key, "last_message_at", 0).ignore();
+    pipe.hset_nx(key, "unread_count", 0).ignore();
+    pipe.sadd("chats", chat_id).ignore();
+    if !instance_id.is_empty() {
+        pipe.cmd("ZADD").arg(instance_chats_key(instance_id)).arg("NX").arg(0).arg(chat_id).ignore();
+    }
+    let _: () = pipe.query_async(redis_conn).await?;
+    report.chats += 1;
+    Ok(())
+}
+
+async fn migrate_messages(redis_conn: &mut ConnectionManager, key: &str, report: &mut RedisMigrationReport) -> redis::RedisResult<()> {
+    let chat_id = key.trim_start_matches("chat:").trim_end_matches(":messages");
+    let chat_key = format!("chat:{}", chat_id);
+    let items: Vec<String> = redis_conn.lrange(key, 0, -1).await?;
+    let instance_id: Option<String> = redis_conn.hget(&chat_key, "instance_id").await.unwrap_or(None);
+
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    pipe.del(key).ignore();
+    let mut last_message_at = 0;
+    for (index, item) in items.iter().enumerate() {
+        let Ok(message) = serde_json::from_str::<Value>(item) else {
+            warn!("Skipping non-JSON message #{} in {}", index, key);
+            report.skipped += 1;
+            continue;
+        };
+        let message_id = match message.get("id").and_then(|v| v.as_str()) {
+            Some(id) if !id.is_empty() => id.to_string(),
+            _ => format!("legacy_{}_{}", chat_id, index),
+        };
+        let score = message.get("timestamp")
+            .and_then(|v| v.as_str().map(str::to_string).or_else(|| v.as_i64().map(|t| t.to_string())))
+            .and_then(|t| parse_timestamp(&t))
+            .unwrap_or_else(Utc::now)
+            .timestamp_millis();
+        last_message_at = last_message_at.max(score);
+
+        let mut fields = to_fields(&message);
+        fields.retain(|(field, _)| field != "id");
+        fields.push(("id".to_string(), message_id.clone()));
+        fields.push(("chat_id".to_string(), chat_id.to_string()));
+        let reference = message_ref(instance_id.as_deref().unwrap_or(""), &message_id);
+        pipe.hset_multiple(message_key(&reference), &fields).ignore();
+        pipe.zadd(chat_messages_key(chat_id), &reference, score).ignore();
+        report.messages += 1;
+    }
+    if last_message_at > 0 {
+        pipe.hset(&chat_key, "last_message_at", last_message_at).ignore();
+        if let Some(instance_id) = instance_id.filter(|i| !i.is_empty()) {
+            pipe.cmd("ZADD").arg(instance_chats_key(&instance_id)).arg("GT").arg(last_message_at).arg(chat_id).ignore();
+        }
+    }
+    let _: () = pipe.query_async(redis_conn).await?;
+    Ok(())
+}
+
+pub async fn migrate_list_keys(redis_conn: &mut ConnectionManager) -> redis::RedisResult<RedisMigrationReport> {
+    let mut report = RedisMigrationReport::default();
+    for key in list_keys(redis_conn).await? {
+        info!("Converting {}", key);
+        if key.ends_with(":messages") {
+            migrate_messages(redis_conn, &key, &mut report).await?;
+        } else {
+            migrate_chat(redis_conn, &key, &mut report).await?;
+        }
+    }
+    Ok(report)
+}
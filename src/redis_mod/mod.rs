@@ -1 +1,6 @@
-pub mod redis;
\ No newline at end of file
+pub mod redis;
+pub mod ledger;
+pub mod migrate;
+pub mod merge;
+pub mod results;
+pub mod ratelimit;
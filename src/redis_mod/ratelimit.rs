@@ -0,0 +1,55 @@
+use redis::aio::ConnectionManager;
+use redis::Script;
+use std::sync::LazyLock;
+use std::time::Duration;
+use crate::process::ratelimit::TokenBucket;
+
+// Token buckets refilled from the Redis clock, so replicas with skewed clocks still agree. Tokens are
+// only taken when every bucket has one, and otherwise the script returns how many ms to wait.
+static TAKE_TOKENS: LazyLock<Script> = LazyLock::new(|| Script::new(r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local wait = 0
+local tokens = {}
+for i, key in ipairs(KEYS) do
+    local rate = tonumber(ARGV[i * 2 - 1])
+    local burst = tonumber(ARGV[i * 2])
+    local state = redis.call('HMGET', key, 'tokens', 'ts')
+    local available = tonumber(state[1]) or burst
+    local last = tonumber(state[2]) or now
+    available = math.min(burst, available + math.max(0, now - last) * rate)
+    tokens[i] = available
+    if available < 1 then
+        wait = math.max(wait, math.ceil((1 - available) / rate))
+    end
+end
+if wait > 0 then
+    return wait
+end
+for i, key in ipairs(KEYS) do
+    local rate = tonumber(ARGV[i * 2 - 1])
+    local burst = tonumber(ARGV[i * 2])
+    redis.call('HSET', key, 'tokens', tostring(tokens[i] - 1), 'ts', now)
+    redis.call('PEXPIRE', key, math.ceil(burst / rate) + 1000)
+end
+return 0
+"#));
+
+// The `{instance}` hash tag keeps both buckets of an instance on the same cluster slot.
+pub fn instance_bucket_key(instance: &str) -> String {
+    format!("ratelimit:instance:{{{}}}", instance)
+}
+
+pub fn number_bucket_key(instance: &str, number: &str) -> String {
+    format!("ratelimit:number:{{{}}}:{}", instance, number)
+}
+
+pub async fn take_tokens(redis_conn: &mut ConnectionManager, buckets: &[(String, TokenBucket)]) -> redis::RedisResult<Duration> {
+    let mut invocation = TAKE_TOKENS.prepare_invoke();
+    for (key, bucket) in buckets {
+        // Rates go in tokens per millisecond to match the Redis clock.
+        invocation.key(key).arg(bucket.per_second / 1000.0).arg(bucket.burst);
+    }
+    let wait: u64 = invocation.invoke_async(redis_conn).await?;
+    Ok(Duration::from_millis(wait))
+}
@@ -1,94 +1,217 @@
-use redis::aio::MultiplexedConnection;
-use redis::AsyncCommands;
-use serde_json::json;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use redis::{AsyncCommands, Script};
+use serde_json::{json, Value};
 use log::{info, error, debug};
+use std::sync::LazyLock;
+use std::time::Duration;
+use chrono::Utc;
+use crate::parser::content::parse_timestamp;
+use crate::parser::library::NormalizedMessage;
+use crate::parser::status::{MessageStatus, StatusUpdate};
+use crate::phone::jid::Jid;
 
-pub async fn connect_redis(redis_url: &str) -> redis::RedisResult<MultiplexedConnection> {
+pub async fn connect_redis(redis_url: &str) -> redis::RedisResult<ConnectionManager> {
     let client = redis::Client::open(redis_url)?;
-    client.get_multiplexed_async_connection().await
+    let config = ConnectionManagerConfig::new()
+        .set_connection_timeout(Duration::from_secs(5))
+        .set_response_timeout(Duration::from_secs(5))
+        .set_number_of_retries(5)
+        .set_max_delay(10_000);
+    client.get_connection_manager_with_config(config).await
 }
 
-pub fn normalize_chat_id(jid: &str) -> String {
-    if let Some((number, domain)) = jid.split_once('@') {
-        if number.starts_with("55") && number.len() >= 12 {
-            let country_code = &number[..2];
-            let area_code = &number[2..4];
-            let rest = &number[4..];
-            let rest = if rest.starts_with('9') {
-                rest.to_string()
-            } else {
-                format!("9{}", rest)
-            };
-            let normalized_number = format!("{}{}{}", country_code, area_code, rest);
-            return format!("{}@{}", normalized_number, domain);
+pub async fn connect_redis_with_retry(redis_url: &str) -> ConnectionManager {
+    loop {
+        match connect_redis(redis_url).await {
+            Ok(conn) => {
+                info!("Redis connection established");
+                return conn;
+            }
+            Err(e) => {
+                error!("ERROR: Couldn't connect to Redis, retrying in 5 seconds... : {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
         }
     }
-    jid.to_string()
+}
+
+pub fn chat_key(chat_id: &str) -> String {
+    format!("chat:{}", chat_id)
+}
+
+pub fn chat_messages_key(chat_id: &str) -> String {
+    format!("chat:{}:messages", chat_id)
+}
+
+// Group messages keep their WhatsApp id on every instance in the group, so each instance gets its own
+// copy; this reference is both the member in `chat:{id}:messages` and the suffix of the message hash.
+pub fn message_ref(instance_id: &str, message_id: &str) -> String {
+    if instance_id.is_empty() {
+        return message_id.to_string();
+    }
+    format!("{}:{}", instance_id, message_id)
+}
+
+pub fn message_key(message_ref: &str) -> String {
+    format!("message:{}", message_ref)
+}
+
+pub fn instance_chats_key(instance_id: &str) -> String {
+    format!("instance:{}:chats", instance_id)
+}
+
+pub fn to_fields(value: &Value) -> Vec<(String, String)> {
+    let Some(object) = value.as_object() else {
+        return Vec::new();
+    };
+    object
+        .iter()
+        .map(|(field, value)| {
+            let value = match value {
+                Value::Null => String::new(),
+                Value::String(v) => v.clone(),
+                other => other.to_string(),
+            };
+            (field.clone(), value)
+        })
+        .collect()
 }
 
 pub async fn ensure_chat_exists(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &mut ConnectionManager,
     chat_id: &str,
     remote_jid: &str,
-    chat_metadata: Option<&str>,
-    message_data: Option<&[u8]>,
+    instance_id: Option<&str>,
+) -> redis::RedisResult<bool> {
+    let chat_key = chat_key(chat_id);
+    let jid = Jid::parse(remote_jid);
+    let number = if jid.is_user() { jid.user.as_str() } else { "" };
+    let defaults = [
+        ("id", chat_id),
+        ("kind", jid.kind.as_str()),
+        ("situation", "enqueued"),
+        ("is_active", "true"),
+        ("agent_id", ""),
+        ("tabulation", ""),
+        ("instance_id", instance_id.unwrap_or("")),
+        ("number", number),
+        ("last_message_at", "0"),
+        ("unread_count", "0"),
+    ];
+
+    let mut pipe = redis::pipe();
+    pipe.atomic();
+    for (field, value) in defaults {
+        pipe.hset_nx(&chat_key, field, value).ignore();
+    }
+    pipe.sadd("chats", chat_id);
+    if let Some(instance_id) = instance_id.filter(|i| !i.is_empty()) {
+        pipe.hset(&chat_key, "instance_id", instance_id).ignore();
+        pipe.cmd("ZADD").arg(instance_chats_key(instance_id)).arg("NX").arg(0).arg(chat_id).ignore();
+    }
+    let (added,): (i64,) = pipe.query_async(redis_conn).await?;
+    debug!("Ensured chat hash exists in Redis: {}", chat_key);
+    Ok(added == 1)
+}
+
+pub async fn update_chat_fields(
+    redis_conn: &mut ConnectionManager,
+    chat_id: &str,
+    fields: &[(String, String)],
 ) -> redis::RedisResult<()> {
-    let norm_chat_id = normalize_chat_id(chat_id);
-    let chat_key = format!("chat:{}", norm_chat_id);
-    let exists: bool = redis_conn.exists(&chat_key).await?;
-    
-    if !exists {
-        let chat_data = if let Some(meta) = chat_metadata {
-            meta.to_string()
-        } else {
-            let number = remote_jid.split('@').next().unwrap_or("").to_string();
-            let instance_id = if let Some(data) = message_data {
-                if let Ok(value) = serde_json::from_slice::<serde_json::Value>(data) {
-                    value.get("apikey").and_then(|v| v.as_str()).unwrap_or("").to_string()
-                } else {
-                    "".to_string()
-                }
-            } else {
-                "".to_string()
-            };
-            json!({
-                "id": norm_chat_id,
-                "situation": "enqueued",
-                "is_active": true,
-                "agent_id": null,
-                "tabulation": null,
-                "instance_id": instance_id,
-                "number": number
-            }).to_string()
-        };
-        let _: isize = redis_conn.rpush(&chat_key, chat_data).await?;
-        info!("Created new chat entry in Redis (as list): {}", chat_key);
-        let _: () = redis_conn.sadd("chats", &norm_chat_id).await?;
-        info!("Added chat_id {} to 'chats' set", norm_chat_id);
-    } else {
-        debug!("Chat entry already exists in Redis: {}", chat_key);
+    if fields.is_empty() {
+        return Ok(());
     }
+    let _: () = redis_conn.hset_multiple(chat_key(chat_id), fields).await?;
+    info!("Updated {} fields on chat:{}", fields.len(), chat_id);
     Ok(())
 }
 
+// Adding the message and deciding whether it counts as unread happen in one script, so two deliveries
+// of the same message can't both see it as new.
+// KEYS: message hash, chat messages, chat hash, [instance chats]
+// ARGV: message ref, score, count as unread (0/1), chat id, field/value pairs...
+static INSERT_MESSAGE: LazyLock<Script> = LazyLock::new(|| Script::new(r#"
+local score = tonumber(ARGV[2])
+local added = redis.call('ZADD', KEYS[2], score, ARGV[1])
+redis.call('HSET', KEYS[1], unpack(ARGV, 5))
+local last = tonumber(redis.call('HGET', KEYS[3], 'last_message_at'))
+if not last or score > last then
+    redis.call('HSET', KEYS[3], 'last_message_at', score)
+end
+if added == 1 and ARGV[3] == '1' then
+    redis.call('HINCRBY', KEYS[3], 'unread_count', 1)
+end
+if KEYS[4] then
+    redis.call('ZADD', KEYS[4], 'GT', score, ARGV[4])
+end
+return added
+"#));
+
 pub async fn insert_message_to_chat(
-    redis_conn: &mut MultiplexedConnection,
+    redis_conn: &mut ConnectionManager,
     chat_id: &str,
-    message_json: &str,
-    remote_jid: &str,
-    chat_metadata: Option<&str>,
-    message_data: Option<&[u8]>,
-) -> redis::RedisResult<()> {
-    let norm_chat_id = normalize_chat_id(chat_id);
-    info!("Inserting message into chat:{} for remote_jid:{}", norm_chat_id, remote_jid);
-    if let Err(e) = ensure_chat_exists(redis_conn, &norm_chat_id, remote_jid, chat_metadata, message_data).await {
-        error!("Failed to ensure chat exists: {}", e);
-        return Err(e);
+    message: &NormalizedMessage,
+    instance_id: &str,
+    from_me: bool,
+) -> redis::RedisResult<bool> {
+    info!("Inserting message {} into chat:{}", message.id, chat_id);
+    let chat_created = match ensure_chat_exists(redis_conn, chat_id, chat_id, Some(instance_id)).await {
+        Ok(created) => created,
+        Err(e) => {
+            error!("Failed to ensure chat exists: {}", e);
+            return Err(e);
+        }
+    };
+
+    let score = parse_timestamp(&message.timestamp)
+        .unwrap_or_else(Utc::now)
+        .timestamp_millis();
+    let mut fields = to_fields(&json!(message));
+    fields.push(("chat_id".to_string(), chat_id.to_string()));
+    fields.push(("instance_id".to_string(), instance_id.to_string()));
+
+    let reference = message_ref(instance_id, &message.id);
+    let mut invocation = INSERT_MESSAGE.prepare_invoke();
+    invocation
+        .key(message_key(&reference))
+        .key(chat_messages_key(chat_id))
+        .key(chat_key(chat_id));
+    if !instance_id.is_empty() {
+        invocation.key(instance_chats_key(instance_id));
     }
-    let key = format!("chat:{}:messages", norm_chat_id);
-    debug!("Pushing message to Redis list: {}", key);
-    let _: isize = redis_conn.rpush(&key, message_json).await?;
-    info!("Successfully inserted message into Redis for chat:{}", norm_chat_id);
-    Ok(())
+    invocation.arg(&reference).arg(score).arg(if from_me { 0 } else { 1 }).arg(chat_id);
+    for (field, value) in &fields {
+        invocation.arg(field).arg(value);
+    }
+    let _: i64 = invocation.invoke_async(redis_conn).await?;
+    info!("Successfully inserted message into Redis for chat:{}", chat_id);
+    Ok(chat_created)
 }
 
+pub async fn update_message_status(
+    redis_conn: &mut ConnectionManager,
+    message_id: &str,
+    update: &StatusUpdate,
+) -> redis::RedisResult<bool> {
+    let key = message_key(&message_ref(&update.instance_id, message_id));
+    let (exists, current): (bool, Option<String>) = redis::pipe()
+        .exists(&key)
+        .hget(&key, "status")
+        .query_async(redis_conn)
+        .await?;
+    if !exists {
+        debug!("Message {} isn't cached in Redis, skipping status update", message_id);
+        return Ok(false);
+    }
+
+    let timestamp = update.timestamp.timestamp_millis();
+    let mut pipe = redis::pipe();
+    pipe.hset_nx(&key, format!("{}_at", update.status.as_str()), timestamp).ignore();
+    if update.status.supersedes(current.as_deref().and_then(MessageStatus::from_name)) {
+        pipe.hset(&key, "status", update.status.as_str()).ignore();
+        pipe.hset(&key, "status_at", timestamp).ignore();
+    }
+    let _: () = pipe.query_async(redis_conn).await?;
+    Ok(true)
+}
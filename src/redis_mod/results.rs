@@ -0,0 +1,17 @@
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde_json::Value;
+use std::time::Duration;
+
+pub fn request_result_key(correlation_id: &str) -> String {
+    format!("request:result:{}", correlation_id)
+}
+
+pub async fn store_request_result(
+    redis_conn: &mut ConnectionManager,
+    correlation_id: &str,
+    result: &Value,
+    ttl: Duration,
+) -> redis::RedisResult<()> {
+    redis_conn.set_ex(request_result_key(correlation_id), result.to_string(), ttl.as_secs().max(1)).await
+}